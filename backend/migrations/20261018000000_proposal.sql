@@ -0,0 +1,21 @@
+-- Add proposal log type which is used by dry-run scan.
+-- SQLite can't alter check constraint, so the table is recreated.
+
+CREATE TABLE log_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- 0: scan, 1: fix, 2: proposal
+    type INTEGER NOT NULL CHECK (type IN (0, 1, 2)),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    success BOOLEAN NOT NULL,
+    message TEXT,
+    old_metadata json,
+    new_metadata json,
+    source_path TEXT NOT NULL,
+    target_path TEXT,
+    acoustid_score FLOAT,
+    retry_count INT
+);
+
+INSERT INTO log_new SELECT * FROM log;
+DROP TABLE log;
+ALTER TABLE log_new RENAME TO log;
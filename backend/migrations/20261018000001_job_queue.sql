@@ -0,0 +1,12 @@
+-- Persisted job queue
+
+CREATE TABLE IF NOT EXISTS job_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    task json NOT NULL,
+    -- 0: pending, 1: running, 2: done, 3: failed
+    state INTEGER NOT NULL CHECK (state IN (0, 1, 2, 3)),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS job_queue_state ON job_queue (state);
@@ -0,0 +1,7 @@
+-- Cache of responses from musicbrainz, acoustid and cover art archive
+
+CREATE TABLE IF NOT EXISTS response_cache (
+    url TEXT PRIMARY KEY NOT NULL,
+    body BLOB NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,17 @@
+-- Top candidates of each scan, so that user can pick another match.
+
+CREATE TABLE IF NOT EXISTS scan_candidate (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    log_id INTEGER NOT NULL,
+    -- 0 is the best match
+    rank INTEGER NOT NULL,
+    recording_id TEXT NOT NULL,
+    recording_title TEXT NOT NULL,
+    release_id TEXT NOT NULL,
+    release_title TEXT NOT NULL,
+    artist TEXT,
+    score FLOAT NOT NULL,
+    score_breakdown json NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS scan_candidate_log_id ON scan_candidate (log_id);
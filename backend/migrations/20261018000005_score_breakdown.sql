@@ -0,0 +1,3 @@
+-- Score breakdown of the selected match
+
+ALTER TABLE log ADD COLUMN score_breakdown json;
@@ -0,0 +1,20 @@
+-- Fingerprints queued for submission to acoustid
+
+CREATE TABLE IF NOT EXISTS acoustid_submission (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    recording_id TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    duration INTEGER NOT NULL,
+    source_path TEXT NOT NULL,
+    -- 0: queued, 1: submitted, 2: imported, 3: failed
+    state INTEGER NOT NULL CHECK (state IN (0, 1, 2, 3)),
+    -- Submission id returned by acoustid
+    submission_id INTEGER,
+    -- Acoustid track id which the fingerprint was imported to
+    acoustid_id TEXT,
+    message TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS acoustid_submission_state ON acoustid_submission (state);
@@ -0,0 +1,3 @@
+-- Cache keys no longer include api keys. Remove old entries which have them.
+
+DELETE FROM response_cache WHERE url LIKE '%client=%';
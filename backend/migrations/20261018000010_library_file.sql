@@ -0,0 +1,23 @@
+-- Musicbrainz ids of files saved by tagbrain, used to detect duplicates.
+-- Unlike logs, this is not cleared by users.
+
+CREATE TABLE IF NOT EXISTS library_file (
+    path TEXT PRIMARY KEY NOT NULL,
+    recording_id TEXT NOT NULL,
+    release_id TEXT NOT NULL,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS library_file_ids ON library_file (recording_id, release_id);
+
+-- 0: scan, 1: fix, 4: refresh
+INSERT OR REPLACE INTO library_file (path, recording_id, release_id)
+SELECT
+    target_path,
+    json_extract(new_metadata, '$.musicbrainz_recording_id'),
+    json_extract(new_metadata, '$.musicbrainz_release_id')
+FROM log
+WHERE success = true AND type IN (0, 1, 4) AND target_path IS NOT NULL
+    AND json_extract(new_metadata, '$.musicbrainz_recording_id') IS NOT NULL
+    AND json_extract(new_metadata, '$.musicbrainz_release_id') IS NOT NULL
+ORDER BY id;
@@ -1,9 +1,16 @@
-use crate::config::CONFIG;
+use std::time::Duration;
 
-use super::deserialize;
+use eyre::eyre;
+
+use crate::config::{Endpoint, CONFIG};
+
+use super::{cache::get_or_fetch, deserialize, RateLimiter};
+
+static RATE_LIMITER: RateLimiter = RateLimiter::new();
 
 pub struct AcoustidClient {
     client: reqwest::Client,
+    endpoint: Endpoint,
 }
 
 #[derive(serde::Deserialize, Debug)]
@@ -16,60 +23,202 @@ pub struct LookupResEntry {
     pub recordings: Option<Vec<LookupResEntryRecording>>,
     pub score: f64,
 }
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 pub struct LookupResEntryRecording {
     pub id: String,
+    pub title: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+    pub artists: Option<Vec<LookupResArtist>>,
+    pub releasegroups: Option<Vec<LookupResReleaseGroup>>,
+}
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LookupResArtist {
+    pub id: String,
+    pub name: String,
+    pub joinphrase: Option<String>,
+}
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LookupResReleaseGroup {
+    pub id: String,
+    pub title: Option<String>,
+    pub r#type: Option<String>,
+    pub secondarytypes: Option<Vec<String>>,
+    pub releases: Option<Vec<LookupResRelease>>,
+}
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LookupResRelease {
+    pub id: String,
+    pub title: Option<String>,
+    pub country: Option<String>,
+    pub date: Option<LookupResDate>,
+    pub mediums: Option<Vec<LookupResMedium>>,
+}
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LookupResDate {
+    pub year: Option<u32>,
+    pub month: Option<u32>,
+    pub day: Option<u32>,
+}
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct LookupResMedium {
+    pub format: Option<String>,
+}
+
+impl LookupResDate {
+    /// Format as musicbrainz date (`YYYY`, `YYYY-MM` or `YYYY-MM-DD`).
+    pub fn to_date_string(&self) -> Option<String> {
+        let year = self.year?;
+        Some(match (self.month, self.day) {
+            (Some(month), Some(day)) => format!("{:04}-{:02}-{:02}", year, month, day),
+            (Some(month), None) => format!("{:04}-{:02}", year, month),
+            _ => format!("{:04}", year),
+        })
+    }
 }
 
 #[derive(serde::Deserialize, Debug)]
 pub struct SubmitRes {
     pub status: String,
+    pub submissions: Option<Vec<SubmissionRes>>,
+    pub error: Option<ErrorRes>,
+}
+#[derive(serde::Deserialize, Debug)]
+pub struct SubmissionStatusRes {
+    pub status: String,
+    pub submissions: Option<Vec<SubmissionRes>>,
+    pub error: Option<ErrorRes>,
+}
+#[derive(serde::Deserialize, Debug)]
+pub struct SubmissionRes {
+    pub id: i64,
+    /// `pending` or `imported`
+    pub status: String,
+    pub result: Option<SubmissionResult>,
+}
+#[derive(serde::Deserialize, Debug)]
+pub struct SubmissionResult {
+    /// Acoustid track id
+    pub id: String,
+}
+#[derive(serde::Deserialize, Debug)]
+pub struct ErrorRes {
+    pub code: i32,
+    pub message: String,
+}
+
+/// Fingerprint to submit.
+#[derive(Debug)]
+pub struct Submission {
+    pub recording_id: String,
+    pub fingerprint: String,
+    pub duration: u32,
 }
 
 impl AcoustidClient {
     pub fn new() -> Self {
+        let config = CONFIG.read();
         let client = reqwest::ClientBuilder::new()
-            .user_agent(&CONFIG.read().app_ua)
+            .user_agent(&config.app_ua)
             .build()
             .unwrap();
-        Self { client }
+        Self {
+            client,
+            endpoint: config.endpoints.acoustid.clone(),
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.endpoint.interval_ms)
     }
 
     #[tracing::instrument(skip(self))]
     pub async fn lookup(&self, fingerprint: &str, duration: u32) -> Result<LookupRes, eyre::Error> {
-        let url = "https://api.acoustid.org/v2/lookup";
+        let url = self.endpoint.url("lookup");
         let url = url::Url::parse_with_params(
-            url,
+            &url,
             &[
                 ("client", &CONFIG.read().acoust_id_api_key),
-                ("meta", &"recordingids".to_string()),
+                ("meta", &"recordings releases releasegroups".to_string()),
                 ("duration", &duration.to_string()),
                 ("fingerprint", &fingerprint.to_string()),
             ],
         )?;
-        let text = self.client.get(url).send().await?.text().await?;
+        let body = get_or_fetch(&self.client, url, Some((&RATE_LIMITER, self.interval()))).await?;
+        let text = String::from_utf8(body)?;
         let res: LookupRes = deserialize(&text)?;
         Ok(res)
     }
 
+    /// Submit multiple fingerprints in one request.
+    /// Returned submissions are in the same order as `submissions`.
+    #[tracing::instrument(skip(self, user_api_key, submissions))]
     pub async fn submit(
         &self,
-        musicbrainz_recording_id: &str,
-        fingerprint: &str,
-        duration: u32,
-    ) -> Result<SubmitRes, eyre::Error> {
-        let url = "https://api.acoustid.org/v2/submit";
-        let url = url::Url::parse_with_params(
-            url,
-            &[
-                ("client", &CONFIG.read().acoust_id_api_key),
-                ("duration", &duration.to_string()),
-                ("fingerprint", &fingerprint.to_string()),
-                ("mbid", &musicbrainz_recording_id.to_string()),
-            ],
-        )?;
-        let text = self.client.get(url).send().await?.text().await?;
+        user_api_key: &str,
+        submissions: &[Submission],
+    ) -> Result<Vec<SubmissionRes>, eyre::Error> {
+        let mut params = vec![
+            (
+                "client".to_string(),
+                CONFIG.read().acoust_id_api_key.clone(),
+            ),
+            ("user".to_string(), user_api_key.to_string()),
+            ("format".to_string(), "json".to_string()),
+        ];
+        for (i, submission) in submissions.iter().enumerate() {
+            params.push((format!("mbid.{}", i), submission.recording_id.clone()));
+            params.push((format!("fingerprint.{}", i), submission.fingerprint.clone()));
+            params.push((format!("duration.{}", i), submission.duration.to_string()));
+        }
+        RATE_LIMITER.wait(self.interval()).await;
+        // Fingerprints are too long for query string.
+        let text = self
+            .client
+            .post(self.endpoint.url("submit"))
+            .form(&params)
+            .send()
+            .await?
+            .text()
+            .await?;
         let res: SubmitRes = deserialize(&text)?;
-        Ok(res)
+        if let Some(error) = res.error {
+            return Err(eyre!(
+                "Acoustid returned error {}: {}",
+                error.code,
+                error.message
+            ));
+        }
+        let submissions_res = res.submissions.unwrap_or_default();
+        if res.status != "ok" || submissions_res.len() != submissions.len() {
+            return Err(eyre!("Unexpected submit response: {}", text));
+        }
+        Ok(submissions_res)
+    }
+
+    /// Get status of submissions.
+    #[tracing::instrument(skip(self))]
+    pub async fn submission_status(&self, ids: &[i64]) -> Result<Vec<SubmissionRes>, eyre::Error> {
+        let url = self.endpoint.url("submission_status");
+        let mut params = vec![
+            (
+                "client".to_string(),
+                CONFIG.read().acoust_id_api_key.clone(),
+            ),
+            ("format".to_string(), "json".to_string()),
+        ];
+        params.extend(ids.iter().map(|id| ("id".to_string(), id.to_string())));
+        let url = url::Url::parse_with_params(&url, &params)?;
+        RATE_LIMITER.wait(self.interval()).await;
+        let text = self.client.get(url).send().await?.text().await?;
+        let res: SubmissionStatusRes = deserialize(&text)?;
+        if let Some(error) = res.error {
+            return Err(eyre!(
+                "Acoustid returned error {}: {}",
+                error.code,
+                error.message
+            ));
+        }
+        Ok(res.submissions.unwrap_or_default())
     }
 }
@@ -0,0 +1,166 @@
+//! Cache of responses from external apis, stored in database and keyed by url.
+
+use std::time::Duration;
+
+use sqlx::query;
+use tracing::{debug, warn};
+
+use crate::{config::CONFIG, POOL};
+
+use super::RateLimiter;
+
+pub struct CacheInfo {
+    pub count: i64,
+    pub expired_count: i64,
+    /// Total size of cached responses in bytes
+    pub size: i64,
+}
+
+/// Query parameters holding api keys. They are removed from cache keys so that secrets are not
+/// stored in database and changing the key doesn't invalidate cache.
+const SECRET_PARAMS: &[&str] = &["client"];
+
+fn cache_key(url: &url::Url) -> String {
+    let mut key = url.clone();
+    let params = url
+        .query_pairs()
+        .filter(|(name, _)| !SECRET_PARAMS.contains(&name.as_ref()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    if params.is_empty() {
+        key.set_query(None);
+    } else {
+        key.query_pairs_mut().clear().extend_pairs(params);
+    }
+    key.to_string()
+}
+
+fn ttl_modifier(ttl: u64) -> String {
+    format!("-{} seconds", ttl)
+}
+
+/// Send GET request and return response body.
+/// If the response is in cache and not expired, request is not sent.
+pub async fn get_or_fetch(
+    client: &reqwest::Client,
+    url: url::Url,
+    rate_limiter: Option<(&RateLimiter, Duration)>,
+) -> eyre::Result<Vec<u8>> {
+    let cache_config = CONFIG.read().cache.clone();
+    let key = cache_key(&url);
+
+    if cache_config.enabled {
+        match get(&key, cache_config.ttl).await {
+            Ok(Some(body)) => {
+                debug!("Cache hit: {}", key);
+                return Ok(body);
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read cache: {:?}", e),
+        }
+    }
+
+    if let Some((rate_limiter, interval)) = rate_limiter {
+        rate_limiter.wait(interval).await;
+    }
+    let body = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?
+        .to_vec();
+
+    if cache_config.enabled {
+        if let Err(e) = put(&key, &body).await {
+            warn!("Failed to write cache: {:?}", e);
+        }
+    }
+
+    Ok(body)
+}
+
+async fn get(key: &str, ttl: u64) -> Result<Option<Vec<u8>>, sqlx::Error> {
+    let modifier = ttl_modifier(ttl);
+    let row = query!(
+        "SELECT body FROM response_cache WHERE url = ? AND created_at > datetime('now', ?)",
+        key,
+        modifier
+    )
+    .fetch_optional(&*POOL)
+    .await?;
+    Ok(row.map(|row| row.body))
+}
+
+async fn put(key: &str, body: &[u8]) -> Result<(), sqlx::Error> {
+    query!(
+        "INSERT OR REPLACE INTO response_cache (url, body) VALUES (?, ?)",
+        key,
+        body
+    )
+    .execute(&*POOL)
+    .await?;
+    Ok(())
+}
+
+pub async fn info() -> Result<CacheInfo, sqlx::Error> {
+    let modifier = ttl_modifier(CONFIG.read().cache.ttl);
+    let row = query!(
+        r#"
+            SELECT
+                COUNT(*) as "count!: i64",
+                COALESCE(SUM(created_at <= datetime('now', ?)), 0) as "expired_count!: i64",
+                COALESCE(SUM(LENGTH(body)), 0) as "size!: i64"
+            FROM response_cache"#,
+        modifier
+    )
+    .fetch_one(&*POOL)
+    .await?;
+    Ok(CacheInfo {
+        count: row.count,
+        expired_count: row.expired_count,
+        size: row.size,
+    })
+}
+
+/// Remove cached responses. If `expired_only` is true, only expired responses are removed.
+pub async fn purge(expired_only: bool) -> Result<(), sqlx::Error> {
+    if expired_only {
+        let modifier = ttl_modifier(CONFIG.read().cache.ttl);
+        query!(
+            "DELETE FROM response_cache WHERE created_at <= datetime('now', ?)",
+            modifier
+        )
+        .execute(&*POOL)
+        .await?;
+    } else {
+        query!("DELETE FROM response_cache").execute(&*POOL).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn cache_key() {
+        let cases = [
+            (
+                "https://api.acoustid.org/v2/lookup?client=secret&duration=100",
+                "https://api.acoustid.org/v2/lookup?duration=100",
+            ),
+            (
+                "https://api.acoustid.org/v2/lookup?client=secret",
+                "https://api.acoustid.org/v2/lookup",
+            ),
+            (
+                "https://musicbrainz.org/ws/2/release/abc?inc=recordings",
+                "https://musicbrainz.org/ws/2/release/abc?inc=recordings",
+            ),
+        ];
+        for (url, expected) in cases {
+            let url = url::Url::parse(url).unwrap();
+            assert_eq!(super::cache_key(&url), expected);
+        }
+    }
+}
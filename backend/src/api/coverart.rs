@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use crate::config::{Endpoint, CONFIG};
+
+use super::{cache::get_or_fetch, deserialize, RateLimiter};
+
+static RATE_LIMITER: RateLimiter = RateLimiter::new();
+
+pub struct CoverArtClient {
+    client: reqwest::Client,
+    endpoint: Endpoint,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CoverArtEntity {
+    Release,
+    ReleaseGroup,
+}
+
+impl CoverArtEntity {
+    fn path(self) -> &'static str {
+        match self {
+            CoverArtEntity::Release => "release",
+            CoverArtEntity::ReleaseGroup => "release-group",
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ImagesRes {
+    pub images: Vec<ImagesResImage>,
+}
+#[derive(serde::Deserialize, Debug)]
+pub struct ImagesResImage {
+    /// e.g. `Front`, `Back`, `Booklet`
+    pub types: Vec<String>,
+    pub image: String,
+    pub thumbnails: ImagesResThumbnails,
+}
+#[derive(serde::Deserialize, Debug)]
+pub struct ImagesResThumbnails {
+    #[serde(rename = "250")]
+    pub small: Option<String>,
+    #[serde(rename = "500")]
+    pub medium: Option<String>,
+    #[serde(rename = "1200")]
+    pub large: Option<String>,
+}
+
+impl CoverArtClient {
+    pub fn new() -> Self {
+        let config = CONFIG.read();
+        let client = reqwest::ClientBuilder::new()
+            .user_agent(&config.app_ua)
+            .build()
+            .unwrap();
+        Self {
+            client,
+            endpoint: config.endpoints.cover_art.clone(),
+        }
+    }
+
+    /// Get front image of the release or release group.
+    /// `size` is one of thumbnail sizes (`250`, `500` or `1200`). If None, original image is returned.
+    #[tracing::instrument(skip(self))]
+    pub async fn front(
+        &self,
+        entity: CoverArtEntity,
+        id: &str,
+        size: Option<u32>,
+    ) -> Result<Vec<u8>, eyre::Report> {
+        let path = match size {
+            Some(size) => format!("{}/{}/front-{}", entity.path(), id, size),
+            None => format!("{}/{}/front", entity.path(), id),
+        };
+        self.get(&self.endpoint.url(&path)).await
+    }
+
+    /// Get list of images of the release.
+    #[tracing::instrument(skip(self))]
+    pub async fn images(&self, release_id: &str) -> Result<ImagesRes, eyre::Report> {
+        let body = self
+            .get(&self.endpoint.url(&format!("release/{}", release_id)))
+            .await?;
+        let text = String::from_utf8(body)?;
+        let res: ImagesRes = deserialize(&text)?;
+        Ok(res)
+    }
+
+    /// Get image by url in `ImagesRes`.
+    pub async fn image(&self, url: &str) -> Result<Vec<u8>, eyre::Report> {
+        self.get(url).await
+    }
+
+    async fn get(&self, url: &str) -> Result<Vec<u8>, eyre::Report> {
+        let url = url::Url::parse(url)?;
+        let interval = Duration::from_millis(self.endpoint.interval_ms);
+        get_or_fetch(&self.client, url, Some((&RATE_LIMITER, interval))).await
+    }
+}
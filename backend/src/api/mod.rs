@@ -1,7 +1,12 @@
+use std::time::Duration;
+
 use serde::de::DeserializeOwned;
+use tokio::{sync::Mutex, time::Instant};
 
 #[allow(dead_code)]
 pub mod acoustid;
+pub mod cache;
+pub mod coverart;
 #[allow(dead_code)]
 pub mod musicbrainz;
 
@@ -14,3 +19,27 @@ fn deserialize<T: DeserializeOwned>(
 
     result
 }
+
+/// Process-wide rate limiter which allows one request per interval.
+pub struct RateLimiter {
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub const fn new() -> Self {
+        Self {
+            next: Mutex::const_new(None),
+        }
+    }
+    /// Wait until next request is allowed. Interval is taken per call so that it can be changed by config.
+    pub async fn wait(&self, interval: Duration) {
+        if interval.is_zero() {
+            return;
+        }
+        let mut next = self.next.lock().await;
+        if let Some(next) = *next {
+            tokio::time::sleep_until(next).await;
+        }
+        *next = Some(Instant::now() + interval);
+    }
+}
@@ -1,41 +1,45 @@
-use crate::config::CONFIG;
+use std::time::Duration;
+
+use crate::config::{Endpoint, CONFIG};
+
+use super::{cache::get_or_fetch, RateLimiter};
 pub mod recording;
 pub mod release;
 
+/// This is shared by all clients because jobs run in parallel.
+static RATE_LIMITER: RateLimiter = RateLimiter::new();
+
 pub struct MusicbrainzClient {
     client: reqwest::Client,
-    semaphore: tokio::sync::Semaphore,
+    endpoint: Endpoint,
 }
 
 impl MusicbrainzClient {
     pub fn new() -> Self {
+        let config = CONFIG.read();
         let client = reqwest::ClientBuilder::new()
-            .user_agent(&CONFIG.read().app_ua)
+            .user_agent(&config.app_ua)
             .build()
             .unwrap();
         Self {
             client,
-            semaphore: tokio::sync::Semaphore::new(1),
+            endpoint: config.endpoints.musicbrainz.clone(),
         }
     }
-    pub async fn get(&self, url: url::Url) -> Result<reqwest::Response, reqwest::Error> {
-        let res = self.client.get(url).send().await;
-
-        // musicbrainz api rate limit is 1 request per second...
-        // so we need to wait 1 second before next request and we use semaphore to disable parallel request
-        let _permit = self.semaphore.acquire().await;
-        tokio::time::sleep(std::time::Duration::from_millis(1000)).await;
-        res
+    pub async fn get(&self, url: url::Url) -> Result<String, eyre::Report> {
+        let interval = Duration::from_millis(self.endpoint.interval_ms);
+        let body = get_or_fetch(&self.client, url, Some((&RATE_LIMITER, interval))).await?;
+        Ok(String::from_utf8(body)?)
     }
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ArtistCredit {
     pub artist: ArtistCreditArtist,
     pub joinphrase: Option<String>,
 }
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ArtistCreditArtist {
     pub id: String,
@@ -2,11 +2,13 @@ use crate::api::deserialize;
 
 use super::{ArtistCredit, MusicbrainzClient};
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct RecordingRes {
     pub title: String,
     pub id: String,
+    /// Length in milliseconds
+    pub length: Option<u32>,
     pub releases: Option<Vec<RecordingResRelease>>,
     pub artist_credit: Option<Vec<ArtistCredit>>,
     pub first_release_date: Option<String>,
@@ -15,49 +17,73 @@ pub struct RecordingRes {
 #[serde(rename_all = "kebab-case")]
 pub struct RecordingResRelease {
     pub id: String,
+    pub title: String,
     pub country: Option<String>,
+    /// ex: "Official", "Promotion", "Bootleg"
+    pub status: Option<String>,
+    pub date: Option<String>,
+    pub media: Option<Vec<RecordingResReleaseMedia>>,
     pub release_group: RecordingResReleaseGroup,
 }
 #[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
+pub struct RecordingResReleaseMedia {
+    /// ex: "CD", "Digital Media", "12\" Vinyl"
+    pub format: Option<String>,
+}
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
 pub struct RecordingResReleaseGroup {
     pub id: String,
     pub title: String,
     pub primary_type: Option<String>,
+    pub secondary_types: Option<Vec<String>>,
     pub first_release_date: Option<String>,
 }
 
 #[derive(serde::Deserialize, Debug)]
 #[serde(rename_all = "kebab-case")]
 pub struct RecordingSearchRes {
+    /// Total number of results
+    pub count: u32,
+    pub offset: u32,
     pub recordings: Vec<RecordingRes>,
 }
 
 impl MusicbrainzClient {
     #[tracing::instrument(skip(self))]
     pub async fn recording(&self, id: &str) -> Result<RecordingRes, eyre::Report> {
-        let url = format!("https://musicbrainz.org/ws/2/recording/{}", id);
+        let url = self.endpoint.url(&format!("recording/{}", id));
         let url = url::Url::parse_with_params(
             &url,
-            &[("fmt", "json"), ("inc", "releases+release-groups+artists")],
+            &[
+                ("fmt", "json"),
+                ("inc", "releases+release-groups+artists+media"),
+            ],
         )?;
-        let res: RecordingRes = self.client.get(url).send().await?.json().await?;
-        // let text = self.get(url).await?.text().await?;
-        // let debug_res: serde_json::Value = serde_json::from_str(&text)?;
-        // let res = serde_json::from_str::<RecordingRes>(&text);
-        // if let Err(e) = &res {
-        //     warn!("recording dbg {}: {:?}", id, debug_res);
-        // }
+        let text = self.get(url).await?;
+        let res: RecordingRes = deserialize(&text)?;
         Ok(res)
     }
-    pub async fn recording_search(&self, query: &str) -> Result<RecordingSearchRes, eyre::Report> {
-        let url = "https://musicbrainz.org/ws/2/recording";
+    /// Search recordings with lucene query.
+    pub async fn recording_search(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<RecordingSearchRes, eyre::Report> {
+        let url = self.endpoint.url("recording");
         let url = url::Url::parse_with_params(
-            url,
-            &[("fmt", "json"), ("query", query), ("limit", "15")],
+            &url,
+            &[
+                ("fmt", "json"),
+                ("query", query),
+                ("limit", &limit.to_string()),
+                ("offset", &offset.to_string()),
+            ],
         )?;
 
-        let text = self.get(url).await?.text().await?;
+        let text = self.get(url).await?;
         let res: RecordingSearchRes = deserialize(&text)?;
         Ok(res)
     }
@@ -2,7 +2,7 @@ use crate::api::deserialize;
 
 use super::{ArtistCredit, MusicbrainzClient};
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReleaseRes {
     pub id: String,
@@ -25,20 +25,20 @@ pub struct ReleaseResReleaseGroup {
     pub first_release_date: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReleaseResTextRepresentation {
     pub script: Option<String>,
     pub language: Option<String>,
 }
 
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReleaseResLabelInfo {
     // pub catalog_number: String,
     pub label: Option<ReleaseResLabelInfoLabel>,
 }
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReleaseResLabelInfoLabel {
     pub name: String,
@@ -46,7 +46,7 @@ pub struct ReleaseResLabelInfoLabel {
 }
 
 /// Represent of media like CD
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReleaseResMedia {
     pub position: u32,
@@ -54,33 +54,41 @@ pub struct ReleaseResMedia {
     pub tracks: Vec<ReleaseResMediaTrack>,
     pub track_count: u32,
 }
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReleaseResMediaTrack {
     pub number: String,
     pub title: String,
     pub id: String,
     pub position: u32,
+    /// Length of the track in milliseconds.
+    pub length: Option<u32>,
     pub recording: ReleaseResMediaTrackRecording,
 }
-#[derive(serde::Deserialize, Debug)]
+#[derive(serde::Deserialize, Debug, Clone)]
 #[serde(rename_all = "kebab-case")]
 pub struct ReleaseResMediaTrackRecording {
     pub id: String,
+    pub title: String,
+    pub length: Option<u32>,
+    pub artist_credit: Option<Vec<ArtistCredit>>,
 }
 
 impl MusicbrainzClient {
     #[tracing::instrument(skip(self))]
     pub async fn release(&self, id: &str) -> Result<ReleaseRes, eyre::Error> {
-        let url = format!("https://musicbrainz.org/ws/2/release/{}", id);
+        let url = self.endpoint.url(&format!("release/{}", id));
         let url = url::Url::parse_with_params(
             &url,
             &[
                 ("fmt", "json"),
-                ("inc", "artists+recordings+labels+release-groups"),
+                (
+                    "inc",
+                    "artists+recordings+artist-credits+labels+release-groups",
+                ),
             ],
         )?;
-        let text = self.get(url).await?.text().await?;
+        let text = self.get(url).await?;
         let res: ReleaseRes = deserialize(&text)?;
         Ok(res)
     }
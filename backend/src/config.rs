@@ -1,5 +1,6 @@
 use std::{
     ops::{Deref, DerefMut},
+    path::Path,
     sync::RwLock,
 };
 
@@ -17,10 +18,126 @@ pub struct Config {
     pub app_ua: String,
     pub acoustid_match_threshold: f64,
     pub release_selector: ReleaseSelector,
+    /// Scanners used to find candidate recordings of a file.
+    #[serde(default)]
+    pub scanner: ScannerConfig,
     pub allowed_extensions: Vec<String>,
     pub delete_original: bool,
+    /// Write tags to source files directly instead of copying them to `target_dir`.
+    #[serde(default)]
+    pub in_place: InPlaceConfig,
+    /// Used as `on_conflict = "overwrite"` if true and `on_conflict = "skip"` if false,
+    /// unless `on_conflict` is set.
     pub overwrite: bool,
+    /// How to handle a file which already exists in `target_dir` at the same path
+    /// or with the same musicbrainz recording and release ids.
+    #[serde(default)]
+    pub on_conflict: Option<ConflictPolicy>,
+    /// Directory where files replaced by new files are moved to, keeping their path relative to `target_dir`.
+    /// If not set, replaced files are deleted.
+    #[serde(default)]
+    pub replaced_dir: Option<String>,
     pub force: bool,
+    /// If true, files which already have musicbrainz release and recording ids are tagged with
+    /// the current data of these ids without fingerprinting. Takes precedence over `force`.
+    #[serde(default)]
+    pub refresh_existing: bool,
+    /// If true, scan only records proposed changes to log and doesn't touch files.
+    /// Can be overridden per scan request.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Number of files processed in parallel.
+    /// Requests to external apis are still limited by `endpoints.*.interval_ms` in total.
+    /// This is read only at startup, so changes take effect after restart.
+    #[serde(default = "Config::default_concurrency")]
+    pub concurrency: usize,
+    /// If true, files in the same directory are identified together as one release.
+    /// If no release matches the directory, each file is scanned separately.
+    #[serde(default)]
+    pub album_mode: bool,
+    #[serde(default)]
+    pub album_selector: AlbumSelector,
+    /// Maximum number of result pages fetched in musicbrainz search
+    /// when no result on previous pages has score higher than threshold.
+    #[serde(default = "Config::default_search_max_pages")]
+    pub search_max_pages: u32,
+    /// Number of candidates saved with each scan log, which can be picked manually.
+    #[serde(default = "Config::default_candidate_count")]
+    pub candidate_count: usize,
+    /// Cache of responses from musicbrainz, acoustid and cover art archive.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    /// Base urls and rate limits of external apis. Change these to use self-hosted mirrors.
+    #[serde(default)]
+    pub endpoints: EndpointsConfig,
+    /// Template of the path where tagged files are saved, relative to `target_dir`.
+    /// The extension of the source file is appended automatically.
+    ///
+    /// - `{field}`: Value of the field. Available fields are `title`, `artist`, `artist_sort`,
+    ///   `album`, `album_artist`, `album_artist_sort`, `track`, `total_tracks`, `disc`,
+    ///   `total_discs`, `original_date`, `original_year`, `date`, `year`, `label`, `media` and `script`.
+    /// - `{album_artist|artist|"Unknown"}`: First field which has value. Quoted text is used as is.
+    /// - `{track:02}`: Zero-padded number. `{track:auto}` pads to the width of the total count.
+    /// - `[...]`: Rendered only if all fields inside have value.
+    /// - `[?multidisc:...]`, `[!compilation:...]`: Rendered only if the condition is true (`?`) or false (`!`).
+    ///
+    /// Each path component is sanitized separately.
+    #[serde(default = "Config::default_path_template")]
+    pub path_template: String,
+    /// Patterns to extract fields from the path relative to `source_dir`, which are used to search
+    /// musicbrainz when the file has no tags. The first matching pattern is used.
+    ///
+    /// - `{field}`: Text in a path component. Available fields are `artist`, `album`, `title`,
+    ///   `track` and `disc`. `track` and `disc` only match digits.
+    /// - `{_}`: Text which is ignored.
+    /// - `/` separates path components. The extension of the file is removed before matching.
+    #[serde(default = "Config::default_filename_patterns")]
+    pub filename_patterns: Vec<String>,
+    /// Submit fingerprints of files identified without acoustid.
+    #[serde(default)]
+    pub acoustid_submit: AcoustidSubmitConfig,
+    /// Cover art fetched from cover art archive.
+    #[serde(default)]
+    pub cover_art: CoverArtConfig,
+}
+
+impl Config {
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        match self.on_conflict {
+            Some(policy) => policy,
+            None if self.overwrite => ConflictPolicy::Overwrite,
+            None => ConflictPolicy::Skip,
+        }
+    }
+    pub fn default_concurrency() -> usize {
+        4
+    }
+    pub fn default_search_max_pages() -> u32 {
+        3
+    }
+    pub fn default_candidate_count() -> usize {
+        5
+    }
+    pub fn default_filename_patterns() -> Vec<String> {
+        [
+            "{artist}/{album}/{disc}-{track} {title}",
+            "{artist}/{album}/{track} - {title}",
+            "{artist}/{album}/{track}. {title}",
+            "{artist}/{album}/{track} {title}",
+            "{artist} - {album}/{track} - {title}",
+            "{artist} - {album}/{track} {title}",
+            "{track} - {title}",
+            "{artist} - {title}",
+            "{title}",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect()
+    }
+    pub fn default_path_template() -> String {
+        "{album_artist|artist}/{album}/[?multidisc:Disc {disc:auto}/][{track:auto} - ]{title}"
+            .to_string()
+    }
 }
 
 impl Default for Config {
@@ -42,6 +159,7 @@ impl Default for Config {
             .to_string(),
             acoustid_match_threshold: 0.8,
             release_selector: ReleaseSelector::default(),
+            scanner: ScannerConfig::default(),
             allowed_extensions: vec![
                 "flac".to_string(),
                 "mp3".to_string(),
@@ -52,8 +170,24 @@ impl Default for Config {
                 "wav".to_string(),
             ],
             delete_original: false,
+            in_place: InPlaceConfig::default(),
             overwrite: false,
+            on_conflict: None,
+            replaced_dir: None,
             force: true,
+            refresh_existing: false,
+            dry_run: false,
+            concurrency: Config::default_concurrency(),
+            album_mode: false,
+            album_selector: AlbumSelector::default(),
+            search_max_pages: Config::default_search_max_pages(),
+            candidate_count: Config::default_candidate_count(),
+            cache: CacheConfig::default(),
+            endpoints: EndpointsConfig::default(),
+            path_template: Config::default_path_template(),
+            filename_patterns: Config::default_filename_patterns(),
+            acoustid_submit: AcoustidSubmitConfig::default(),
+            cover_art: CoverArtConfig::default(),
         }
     }
 }
@@ -74,6 +208,26 @@ pub struct ReleaseSelector {
     /// Read metadata from current file and calculate levenshtein distance.
     pub release_title_distance: DistanceReleaseSelector,
     pub recording_title_distance: DistanceReleaseSelector,
+    /// Compare duration of the file and the recording.
+    #[serde(default)]
+    pub duration_distance: DurationReleaseSelector,
+    /// ex: ["Official"]
+    #[serde(default)]
+    pub status: MatchReleaseSelector,
+    /// Medium format. If the release has multiple media, the most preferred one is used.
+    /// ex: ["CD", "Digital Media"]
+    #[serde(default)]
+    pub format: MatchReleaseSelector,
+    /// Secondary type of the release group. If any of them matches, `weight` is subtracted.
+    /// ref: https://musicbrainz.org/doc/Release_Group/Type
+    #[serde(default)]
+    pub secondary_type_penalty: PenaltyReleaseSelector,
+    #[serde(default)]
+    pub date: DateReleaseSelector,
+    /// Score of acoustid match. Only applied to recordings found by acoustid.
+    /// It affects ranking of candidates but is not counted against `threshold`.
+    #[serde(default)]
+    pub acoustid_score: WeightReleaseSelector,
     pub threshold: f64,
 }
 
@@ -98,6 +252,12 @@ impl Default for ReleaseSelector {
                 threshold: 0.5,
                 weight: 1.0,
             },
+            duration_distance: DurationReleaseSelector::default(),
+            status: MatchReleaseSelector::default(),
+            format: MatchReleaseSelector::default(),
+            secondary_type_penalty: PenaltyReleaseSelector::default(),
+            date: DateReleaseSelector::default(),
+            acoustid_score: WeightReleaseSelector::default(),
             threshold: 1.0,
         }
     }
@@ -108,6 +268,67 @@ pub struct MatchReleaseSelector {
     pub preferred: Vec<String>,
     pub weight: f64,
 }
+
+impl Default for MatchReleaseSelector {
+    fn default() -> Self {
+        Self {
+            preferred: vec![],
+            weight: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PenaltyReleaseSelector {
+    pub penalized: Vec<String>,
+    pub weight: f64,
+}
+
+impl Default for PenaltyReleaseSelector {
+    fn default() -> Self {
+        Self {
+            // ex: ["Compilation", "Live", "Remix"]
+            penalized: vec![],
+            weight: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WeightReleaseSelector {
+    pub weight: f64,
+}
+
+impl Default for WeightReleaseSelector {
+    fn default() -> Self {
+        Self { weight: 1.0 }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DateReleaseSelector {
+    pub prefer: DatePreference,
+    pub weight: f64,
+}
+
+impl Default for DateReleaseSelector {
+    fn default() -> Self {
+        Self {
+            prefer: DatePreference::None,
+            weight: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatePreference {
+    None,
+    /// Prefer the earliest release of the recording.
+    Earliest,
+    /// Prefer the first release in the release group, rather than reissues.
+    Original,
+}
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DistanceReleaseSelector {
     /// If value is less than this, the score will be 0.
@@ -115,6 +336,269 @@ pub struct DistanceReleaseSelector {
     pub weight: f64,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ScannerConfig {
+    /// Scanners are run in this order.
+    pub chain: Vec<ScannerEntry>,
+    /// If true, every enabled scanner is run and all results are ranked together.
+    /// Otherwise the chain stops at the first scanner which finds a match above its threshold.
+    pub merge: bool,
+}
+
+impl Default for ScannerConfig {
+    fn default() -> Self {
+        Self {
+            chain: [
+                ScannerKind::AcoustId,
+                ScannerKind::MusicbrainzSearch,
+                ScannerKind::Path,
+            ]
+            .into_iter()
+            .map(|kind| ScannerEntry {
+                kind,
+                enabled: true,
+                threshold: None,
+            })
+            .collect(),
+            merge: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ScannerEntry {
+    pub kind: ScannerKind,
+    #[serde(default = "ScannerEntry::default_enabled")]
+    pub enabled: bool,
+    /// Minimum score of matches found by this scanner. If not set, `release_selector.threshold` is used.
+    pub threshold: Option<f64>,
+}
+
+impl ScannerEntry {
+    pub fn default_enabled() -> bool {
+        true
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Don't save the new file.
+    Skip,
+    /// Replace existing files with the new file.
+    Overwrite,
+    /// Save the new file with a numbered suffix.
+    KeepBoth,
+    /// Replace existing files only if the new file has higher quality.
+    /// Lossless is preferred, then bit depth and sample rate for lossless files or bitrate for lossy files.
+    KeepHigherQuality,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum ScannerKind {
+    /// Lookup acoustid with fingerprint of the file.
+    #[serde(rename = "acoustid")]
+    AcoustId,
+    /// Search musicbrainz with tags of the file.
+    #[serde(rename = "musicbrainz_search")]
+    MusicbrainzSearch,
+    /// Search musicbrainz with fields extracted from the path by `filename_patterns`.
+    #[serde(rename = "path")]
+    Path,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DurationReleaseSelector {
+    /// Difference in seconds at which the score becomes 0. Score decreases linearly until this.
+    pub tolerance: f64,
+    pub weight: f64,
+}
+
+impl Default for DurationReleaseSelector {
+    /// Disabled by default so that existing thresholds keep their meaning.
+    fn default() -> Self {
+        Self {
+            tolerance: 10.0,
+            weight: 0.0,
+        }
+    }
+}
+
+/// Rules to select a release for a whole directory in album mode.
+///
+/// Each candidate release gets a score from how many files it covers, how close its track count is
+/// to the number of files, how many files have the same track position in tag, and how close the
+/// track durations are. The release with the highest score is selected.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AlbumSelector {
+    /// Minimum ratio of files which must be matched to tracks of the release.
+    pub min_coverage: f64,
+    /// Duration difference in seconds which is regarded as a different track.
+    pub duration_tolerance: f64,
+    /// Number of releases to fetch from musicbrainz and compare.
+    pub candidate_count: usize,
+}
+
+impl Default for AlbumSelector {
+    fn default() -> Self {
+        Self {
+            min_coverage: 0.5,
+            duration_tolerance: 10.0,
+            candidate_count: 3,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Time to live of cached responses in seconds.
+    pub ttl: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            // 30 days
+            ttl: 60 * 60 * 24 * 30,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct InPlaceConfig {
+    /// If true, all files are tagged in place.
+    pub enabled: bool,
+    /// Directories whose files are tagged in place even if `enabled` is false.
+    /// Relative paths are resolved from `source_dir`.
+    pub dirs: Vec<String>,
+    /// If true, files are renamed within their directory using the last component of `path_template`.
+    pub rename: bool,
+}
+
+impl InPlaceConfig {
+    pub fn is_enabled_for(&self, path: &Path, source_dir: &str) -> bool {
+        self.enabled
+            || self
+                .dirs
+                .iter()
+                .any(|dir| path.starts_with(Path::new(source_dir).join(dir)))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CoverArtConfig {
+    /// Embed cover art into files.
+    pub embed: bool,
+    /// Save front cover as `cover.jpg` in the directory of saved files, unless it already exists.
+    pub folder_image: bool,
+    /// Maximum width of images. The largest thumbnail of cover art archive (250, 500 or 1200)
+    /// which is not larger than this is used, and 250 if it's smaller than 250.
+    /// If not set, original images are used.
+    pub max_size: Option<u32>,
+    /// Embedded front cover narrower than this is replaced. If not set, existing cover art is kept.
+    pub replace_below_width: Option<u32>,
+    /// Types of images embedded in addition to front cover, e.g. `Back` and `Booklet`.
+    /// ref: https://musicbrainz.org/doc/Cover_Art/Types
+    pub extra_types: Vec<String>,
+    /// Use front cover of the release group if the release has none.
+    pub release_group_fallback: bool,
+}
+
+impl Default for CoverArtConfig {
+    fn default() -> Self {
+        Self {
+            embed: true,
+            folder_image: false,
+            max_size: None,
+            replace_below_width: None,
+            extra_types: vec![],
+            release_group_fallback: true,
+        }
+    }
+}
+
+impl CoverArtConfig {
+    /// Thumbnail size of cover art archive to use. None means the original image.
+    pub fn thumbnail_size(&self) -> Option<u32> {
+        self.max_size.map(|max| match max {
+            1200.. => 1200,
+            500.. => 500,
+            _ => 250,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct AcoustidSubmitConfig {
+    pub enabled: bool,
+    /// User api key of acoustid, which is required to submit.
+    /// ref: https://acoustid.org/api-key
+    pub user_api_key: String,
+    /// Maximum number of fingerprints submitted in one request.
+    pub batch_size: usize,
+    /// Interval in seconds to submit queued fingerprints and check status of submitted ones.
+    pub interval: u64,
+}
+
+impl Default for AcoustidSubmitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            user_api_key: String::new(),
+            batch_size: 20,
+            interval: 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct EndpointsConfig {
+    pub musicbrainz: Endpoint,
+    pub acoustid: Endpoint,
+    pub cover_art: Endpoint,
+}
+
+impl Default for EndpointsConfig {
+    fn default() -> Self {
+        Self {
+            musicbrainz: Endpoint {
+                base_url: "https://musicbrainz.org/ws/2".to_string(),
+                // musicbrainz api rate limit is 1 request per second.
+                interval_ms: 1000,
+            },
+            acoustid: Endpoint {
+                base_url: "https://api.acoustid.org/v2".to_string(),
+                // acoustid api rate limit is 3 requests per second.
+                interval_ms: 334,
+            },
+            cover_art: Endpoint {
+                base_url: "https://coverartarchive.org".to_string(),
+                interval_ms: 0,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Endpoint {
+    /// Url which api paths are appended to. ex: `https://musicbrainz.org/ws/2`
+    pub base_url: String,
+    /// Minimum interval between requests in milliseconds. `0` disables rate limiting.
+    pub interval_ms: u64,
+}
+
+impl Endpoint {
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url.trim_end_matches('/'), path)
+    }
+}
+
 static CONFIG_PATH: Lazy<String> =
     Lazy::new(|| std::env::var("CONFIG_PATH").expect("CONFIG_PATH not set"));
 
@@ -178,3 +662,27 @@ pub static CONFIG: Lazy<ConfigWrapper> = Lazy::new(|| {
         config: RwLock::new(config),
     }
 });
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn thumbnail_size() {
+        let cases = [
+            (None, None),
+            (Some(100), Some(250)),
+            (Some(250), Some(250)),
+            (Some(499), Some(250)),
+            (Some(500), Some(500)),
+            (Some(800), Some(500)),
+            (Some(1200), Some(1200)),
+            (Some(5000), Some(1200)),
+        ];
+        for (max_size, expected) in cases {
+            let config = super::CoverArtConfig {
+                max_size,
+                ..Default::default()
+            };
+            assert_eq!(config.thumbnail_size(), expected, "{:?}", max_size);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+/// Score of a release and recording pair calculated with `ReleaseSelector`.
+#[derive(Serialize, Deserialize, specta::Type, Clone, Debug, Default)]
+pub struct ScoreBreakdown {
+    pub components: Vec<ScoreComponent>,
+    pub total: f64,
+    /// Score of acoustid match if the recording was found by fingerprint.
+    /// It's also added to `total` as the `acoustid_score` component with the configured weight,
+    /// but it's not counted when `total` is compared with thresholds.
+    #[serde(default)]
+    pub acoustid_score: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, specta::Type, Clone, Debug)]
+pub struct ScoreComponent {
+    pub name: String,
+    /// Score before weight is applied.
+    pub value: f64,
+    pub weight: f64,
+    /// `value * weight`, which is added to the total.
+    pub contribution: f64,
+}
+
+impl ScoreBreakdown {
+    /// Total score without `acoustid_score` component, which is compared with thresholds.
+    /// Acoustid score only affects ranking so that thresholds have the same meaning for all scanners.
+    pub fn threshold_total(&self) -> f64 {
+        self.total
+            - self
+                .components
+                .iter()
+                .filter(|c| c.name == "acoustid_score")
+                .map(|c| c.contribution)
+                .sum::<f64>()
+    }
+
+    pub fn add(&mut self, name: &str, value: f64, weight: f64) {
+        let contribution = value * weight;
+        self.total += contribution;
+        self.components.push(ScoreComponent {
+            name: name.to_string(),
+            value,
+            weight,
+            contribution,
+        });
+    }
+}
+
+/// Release and recording pair which was considered in scan.
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub recording_id: String,
+    pub recording_title: String,
+    pub release_id: String,
+    pub release_title: String,
+    pub artist: Option<String>,
+    pub score: ScoreBreakdown,
+}
+
+pub struct ScanCandidateRaw {
+    pub id: i64,
+    pub log_id: i64,
+    pub rank: i64,
+    pub recording_id: String,
+    pub recording_title: String,
+    pub release_id: String,
+    pub release_title: String,
+    pub artist: Option<String>,
+    pub score_breakdown: sqlx::types::Json<ScoreBreakdown>,
+}
+
+#[derive(Serialize, specta::Type)]
+pub struct ScanCandidate {
+    pub id: i32,
+    pub log_id: i32,
+    /// 0 is the best match.
+    pub rank: i32,
+    pub recording_id: String,
+    pub recording_title: String,
+    pub release_id: String,
+    pub release_title: String,
+    pub artist: Option<String>,
+    pub score: ScoreBreakdown,
+}
+impl From<ScanCandidateRaw> for ScanCandidate {
+    fn from(raw: ScanCandidateRaw) -> Self {
+        Self {
+            id: raw.id as i32,
+            log_id: raw.log_id as i32,
+            rank: raw.rank as i32,
+            recording_id: raw.recording_id,
+            recording_title: raw.recording_title,
+            release_id: raw.release_id,
+            release_title: raw.release_title,
+            artist: raw.artist,
+            score: raw.score_breakdown.0,
+        }
+    }
+}
@@ -1,10 +1,19 @@
-use super::metadata::Metadata;
+use super::{candidate::ScoreBreakdown, metadata::Metadata};
 
-#[derive(sqlx::Type, specta::Type, serde::Serialize)]
+#[derive(sqlx::Type, specta::Type, serde::Serialize, Clone, Copy)]
 #[repr(i32)]
 pub enum LogType {
     Scan = 0,
     Fix = 1,
+    /// Result of dry-run scan. Files are not touched until it is applied.
+    Proposal = 2,
+    /// Scan or fix which was reverted. `source_path` is the reverted file and `target_path` is the restored file.
+    Revert = 3,
+    /// Tags of the file in `target_dir` re-synced with musicbrainz.
+    Refresh = 4,
+    /// File in target dir replaced by a new file with higher quality or by conflict policy.
+    /// `source_path` is the replaced file and `target_path` is where it was moved to.
+    Replace = 5,
 }
 
 pub struct ScanLogRaw {
@@ -19,6 +28,7 @@ pub struct ScanLogRaw {
     pub target_path: Option<String>,
     pub acoustid_score: Option<f64>,
     pub retry_count: Option<i64>,
+    pub score_breakdown: Option<sqlx::types::Json<ScoreBreakdown>>,
 }
 
 #[derive(serde::Serialize, specta::Type)]
@@ -34,6 +44,8 @@ pub struct ScanLog {
     pub target_path: Option<String>,
     pub acoustid_score: Option<f32>,
     pub retry_count: Option<i32>,
+    /// Score breakdown of the selected match.
+    pub score_breakdown: Option<ScoreBreakdown>,
 }
 impl From<ScanLogRaw> for ScanLog {
     fn from(raw: ScanLogRaw) -> Self {
@@ -49,6 +61,7 @@ impl From<ScanLogRaw> for ScanLog {
             target_path: raw.target_path,
             acoustid_score: raw.acoustid_score.map(|x| x as f32),
             retry_count: raw.retry_count.map(|x| x as i32),
+            score_breakdown: raw.score_breakdown.map(|x| x.0),
         }
     }
 }
@@ -1,7 +1,7 @@
 use lofty::tag::{ItemKey, ItemValue, Tag, TagItem};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize, specta::Type)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, specta::Type)]
 pub struct Metadata {
     pub title: Option<String>,
     pub artist: Option<String>,
@@ -112,3 +112,33 @@ pub fn write_metadata(tag: &mut Tag, metadata: Metadata) {
     insert!(tag, MusicBrainzReleaseGroupId,  metadata.musicbrainz_release_group_id);
     insert!(tag, MusicBrainzRecordingId,     metadata.musicbrainz_recording_id);
 }
+
+/// Remove all fields which are written by [`write_metadata`].
+pub fn remove_metadata(tag: &mut Tag) {
+    for key in [
+        ItemKey::TrackTitle,
+        ItemKey::TrackArtist,
+        ItemKey::TrackArtistSortOrder,
+        ItemKey::AlbumTitle,
+        ItemKey::AlbumArtist,
+        ItemKey::AlbumArtistSortOrder,
+        ItemKey::TrackNumber,
+        ItemKey::TrackTotal,
+        ItemKey::DiscNumber,
+        ItemKey::DiscTotal,
+        ItemKey::OriginalReleaseDate,
+        ItemKey::RecordingDate,
+        ItemKey::Year,
+        ItemKey::Label,
+        ItemKey::OriginalMediaType,
+        ItemKey::Script,
+        ItemKey::MusicBrainzArtistId,
+        ItemKey::MusicBrainzTrackId,
+        ItemKey::MusicBrainzReleaseId,
+        ItemKey::MusicBrainzReleaseArtistId,
+        ItemKey::MusicBrainzReleaseGroupId,
+        ItemKey::MusicBrainzRecordingId,
+    ] {
+        tag.remove_key(&key);
+    }
+}
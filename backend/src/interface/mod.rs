@@ -1,2 +1,3 @@
+pub mod candidate;
 pub mod log;
 pub mod metadata;
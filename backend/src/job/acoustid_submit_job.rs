@@ -0,0 +1,163 @@
+use std::{path::Path, time::Duration};
+
+use sqlx::query;
+use tracing::{error, info, warn};
+
+use crate::{
+    api::acoustid::{AcoustidClient, Submission},
+    config::CONFIG,
+    POOL,
+};
+
+#[derive(Debug, Clone, Copy, sqlx::Type)]
+#[repr(i32)]
+pub enum SubmissionState {
+    /// Waiting to be submitted in next batch.
+    Queued = 0,
+    /// Submitted but not imported yet.
+    Submitted = 1,
+    Imported = 2,
+    Failed = 3,
+}
+
+/// Queue fingerprint to be submitted to acoustid in the next batch.
+pub(super) async fn queue_submission(
+    recording_id: &str,
+    fingerprint: &str,
+    duration: u32,
+    source_path: &Path,
+) -> Result<(), sqlx::Error> {
+    let source_path = source_path.to_string_lossy();
+    query!(
+        "INSERT INTO acoustid_submission (recording_id, fingerprint, duration, source_path, state) VALUES (?, ?, ?, ?, ?)",
+        recording_id,
+        fingerprint,
+        duration,
+        source_path,
+        SubmissionState::Queued
+    )
+    .execute(&*POOL)
+    .await?;
+    Ok(())
+}
+
+/// Periodically submit queued fingerprints and check status of submitted ones.
+pub async fn acoustid_submit_job() {
+    loop {
+        let (enabled, interval) = {
+            let config = CONFIG.read();
+            (
+                config.acoustid_submit.enabled,
+                config.acoustid_submit.interval,
+            )
+        };
+        if enabled {
+            if let Err(e) = submit_queued().await {
+                error!("Failed to submit fingerprints: {:?}", e);
+            }
+            if let Err(e) = update_status().await {
+                error!("Failed to check submission status: {:?}", e);
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(interval.max(1))).await;
+    }
+}
+
+#[tracing::instrument]
+async fn submit_queued() -> eyre::Result<()> {
+    let (user_api_key, batch_size) = {
+        let config = CONFIG.read();
+        (
+            config.acoustid_submit.user_api_key.clone(),
+            config.acoustid_submit.batch_size.max(1) as i64,
+        )
+    };
+    if user_api_key.is_empty() {
+        warn!("acoustid_submit.user_api_key is not set. Skipping submission.");
+        return Ok(());
+    }
+
+    let rows = query!(
+        r#"SELECT id as "id!: i64", recording_id, fingerprint, duration FROM acoustid_submission WHERE state = ? ORDER BY id LIMIT ?"#,
+        SubmissionState::Queued,
+        batch_size
+    )
+    .fetch_all(&*POOL)
+    .await?;
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let submissions = rows
+        .iter()
+        .map(|row| Submission {
+            recording_id: row.recording_id.clone(),
+            fingerprint: row.fingerprint.clone(),
+            duration: row.duration as u32,
+        })
+        .collect::<Vec<_>>();
+    let client = AcoustidClient::new();
+    match client.submit(&user_api_key, &submissions).await {
+        Ok(res) => {
+            info!("Submitted {} fingerprints to acoustid", res.len());
+            for (row, submission) in rows.iter().zip(res) {
+                query!(
+                    "UPDATE acoustid_submission SET state = ?, submission_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+                    SubmissionState::Submitted,
+                    submission.id,
+                    row.id
+                )
+                .execute(&*POOL)
+                .await?;
+            }
+        }
+        Err(e) => {
+            let message = e.to_string();
+            for row in &rows {
+                query!(
+                    "UPDATE acoustid_submission SET state = ?, message = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+                    SubmissionState::Failed,
+                    message,
+                    row.id
+                )
+                .execute(&*POOL)
+                .await?;
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+#[tracing::instrument]
+async fn update_status() -> eyre::Result<()> {
+    let ids = query!(
+        r#"SELECT submission_id as "submission_id!: i64" FROM acoustid_submission WHERE state = ? AND submission_id IS NOT NULL ORDER BY id"#,
+        SubmissionState::Submitted
+    )
+    .fetch_all(&*POOL)
+    .await?
+    .into_iter()
+    .map(|row| row.submission_id)
+    .collect::<Vec<_>>();
+
+    let batch_size = CONFIG.read().acoustid_submit.batch_size.max(1);
+    let client = AcoustidClient::new();
+    for ids in ids.chunks(batch_size) {
+        for submission in client.submission_status(ids).await? {
+            if submission.status != "imported" {
+                continue;
+            }
+            let acoustid_id = submission.result.map(|r| r.id);
+            query!(
+                "UPDATE acoustid_submission SET state = ?, acoustid_id = ?, updated_at = CURRENT_TIMESTAMP WHERE submission_id = ?",
+                SubmissionState::Imported,
+                acoustid_id,
+                submission.id
+            )
+            .execute(&*POOL)
+            .await?;
+        }
+    }
+    Ok(())
+}
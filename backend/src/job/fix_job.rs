@@ -1,9 +1,7 @@
 use std::path::{Path, PathBuf};
 
-use eyre::Context;
-use lofty::{config::WriteOptions, prelude::TagExt};
 use sqlx::query;
-use tracing::{error, info, warn};
+use tracing::{error, info};
 
 use crate::{
     api::musicbrainz::MusicbrainzClient,
@@ -15,12 +13,18 @@ use crate::{
     POOL,
 };
 
-use super::utils::{
-    fetch_cover_art, get_save_path_from_metadata, read_tag_or_default, response_to_metadata,
+use super::{
+    scan_job::save_fixed,
+    utils::{read_tag_or_default, response_to_metadata, CoverArt},
 };
 
 /// fix metadata with manually provided info
-pub async fn fix_job(path: &Path, release_id: String, recording_id: String, copy_to_target: bool) {
+pub async fn fix_job(
+    path: &Path,
+    release_id: String,
+    recording_id: String,
+    copy_to_target: bool,
+) -> bool {
     let res = fix_job_inner(path, release_id, recording_id, copy_to_target).await;
     match res {
         Ok(res) => {
@@ -42,6 +46,7 @@ pub async fn fix_job(path: &Path, release_id: String, recording_id: String, copy
             if let Err(err) = res {
                 error!("Failed to insert log: {:?}", err);
             }
+            true
         }
         Err(e) => {
             let msg = format!("{:?}", e);
@@ -59,6 +64,7 @@ pub async fn fix_job(path: &Path, release_id: String, recording_id: String, copy
             if let Err(err) = res {
                 error!("Failed to insert log: {:?}", err);
             }
+            false
         }
     }
 }
@@ -81,29 +87,27 @@ async fn fix_job_inner(
     let recording = mb.recording(&recording_id).await?;
     let metadata = response_to_metadata(recording, release)?;
 
-    let new_path =
-        get_save_path_from_metadata(path, Path::new(&CONFIG.read().target_dir), &metadata)?;
-    tokio::fs::create_dir_all(new_path.parent().unwrap()).await?;
-    tokio::fs::copy(path, &new_path).await?;
-
-    if !copy_to_target || CONFIG.read().delete_original {
-        tokio::fs::remove_file(path).await?;
-    }
-
-    if tag.picture_count() == 0 {
-        let cover_art = fetch_cover_art(&release_id).await;
+    let cover_art = CoverArt::new(
+        &release_id,
+        metadata.musicbrainz_release_group_id.as_deref(),
+    );
+    cover_art.embed(&mut tag).await;
 
-        match cover_art {
-            Ok(cover_art) => tag.push_picture(cover_art),
-            Err(e) => warn!("Failed to fetch cover art: {}", e),
-        }
-    }
+    let old_metadata = Metadata::from_tag(&tag);
 
     write_metadata(&mut tag, metadata.clone());
-    tag.save_to_path(new_path.clone(), WriteOptions::new())
-        .wrap_err("Failed to write tag")?;
 
-    let old_metadata = Metadata::from_tag(&tag);
+    let (in_place, keep_source) = {
+        let config = CONFIG.read();
+        let in_place = config.in_place.is_enabled_for(path, &config.source_dir)
+            // The file was tagged in place before.
+            || (!copy_to_target && !path.starts_with(&config.target_dir));
+        (in_place, copy_to_target && !config.delete_original)
+    };
+    let new_path = save_fixed(path, tag, &metadata, in_place, keep_source).await?;
+    if let Some(dir) = new_path.parent() {
+        cover_art.write_folder_image(dir).await;
+    }
 
     Ok(FixJobRes {
         old_metadata,
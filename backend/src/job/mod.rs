@@ -1,17 +1,24 @@
 use std::{
-    path::PathBuf,
-    sync::{Arc, Mutex},
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use sqlx::query;
 use tokio::sync::{mpsc, oneshot};
-use tracing::info;
+use tracing::{error, info};
 
-use crate::{config::CONFIG, JobReceiver};
+use crate::{config::CONFIG, JobReceiver, POOL};
 
+mod acoustid_submit_job;
 mod fix_job;
+mod refresh_job;
+mod revert_job;
 mod scan_job;
 mod utils;
 
+pub use utils::{FilenamePattern, PathTemplate};
+
 #[derive(Debug)]
 pub struct QueueInfo {
     pub tasks: Vec<JobTask>,
@@ -23,8 +30,20 @@ pub enum JobCommand {
     Scan {
         path: PathBuf,
         retry_count: u8,
+        /// If None, `dry_run` in config is used.
+        dry_run: Option<bool>,
+    },
+    ScanAll {
+        dry_run: Option<bool>,
+    },
+    ApplyProposals {
+        log_ids: Vec<i32>,
     },
-    ScanAll,
+    Revert {
+        log_id: i32,
+    },
+    /// Re-sync tags of all files in `target_dir` with musicbrainz.
+    RefreshLibrary,
     ClearQueue,
     GetQueueInfo {
         sender: oneshot::Sender<QueueInfo>,
@@ -41,11 +60,27 @@ pub enum JobCommand {
     },
 }
 
-#[derive(Debug, Clone, specta::Type, serde::Serialize)]
+#[derive(Debug, Clone, specta::Type, serde::Serialize, serde::Deserialize)]
 pub enum JobTask {
     Scan {
         path: PathBuf,
         retry_count: u8,
+        dry_run: bool,
+    },
+    /// Scan files in the directory as one release. Used in album mode.
+    ScanAlbum {
+        dir: PathBuf,
+        dry_run: bool,
+    },
+    ApplyProposal {
+        log_id: i32,
+    },
+    /// Restore the original file of scan or fix log.
+    Revert {
+        log_id: i32,
+    },
+    Refresh {
+        path: PathBuf,
     },
     Fix {
         path: PathBuf,
@@ -55,31 +90,164 @@ pub enum JobTask {
     },
 }
 
+#[derive(Debug, Clone, Copy, sqlx::Type)]
+#[repr(i32)]
+pub enum JobState {
+    Pending = 0,
+    Running = 1,
+    Done = 2,
+    Failed = 3,
+}
+
+/// Job queue persisted to database, so that queued tasks survive restarts.
 pub struct Queue {
-    pub queue: Mutex<Vec<JobTask>>,
     pub channel: mpsc::UnboundedSender<()>,
 }
 impl Queue {
     pub fn new() -> (Self, mpsc::UnboundedReceiver<()>) {
         let channel = mpsc::unbounded_channel();
-        (
-            Self {
-                queue: Mutex::new(vec![]),
-                channel: channel.0,
-            },
-            channel.1,
+        (Self { channel: channel.0 }, channel.1)
+    }
+    /// Prepare persisted queue on startup.
+    /// Tasks which were running when the app was stopped are queued again, and finished tasks are removed.
+    pub async fn restore(&self) -> Result<(), sqlx::Error> {
+        query!(
+            "UPDATE job_queue SET state = ?, updated_at = CURRENT_TIMESTAMP WHERE state = ?",
+            JobState::Pending,
+            JobState::Running
+        )
+        .execute(&*POOL)
+        .await?;
+        query!(
+            "DELETE FROM job_queue WHERE state IN (?, ?)",
+            JobState::Done,
+            JobState::Failed
         )
+        .execute(&*POOL)
+        .await?;
+        let pending = query!(
+            "SELECT COUNT(*) as count FROM job_queue WHERE state = ?",
+            JobState::Pending
+        )
+        .fetch_one(&*POOL)
+        .await?
+        .count;
+        if pending > 0 {
+            info!("Restored {} pending tasks", pending);
+            self.channel.send(()).unwrap();
+        }
+        Ok(())
     }
-    pub fn enqueue(&self, item: JobTask) {
-        info!("Enqueue: {:?}", item);
-        self.queue.lock().unwrap().push(item);
-        self.channel.send(()).unwrap();
+    pub async fn enqueue(&self, item: JobTask) {
+        self.enqueue_all(vec![item]).await;
     }
-    pub fn dequeue(&self) -> Option<JobTask> {
-        self.queue.lock().unwrap().pop()
+    pub async fn enqueue_all(&self, items: Vec<JobTask>) {
+        let res = async {
+            let mut tx = POOL.begin().await?;
+            for item in items {
+                info!("Enqueue: {:?}", item);
+                let task = sqlx::types::Json(item);
+                query!(
+                    "INSERT INTO job_queue (task, state) VALUES (?, ?)",
+                    task,
+                    JobState::Pending
+                )
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+        match res {
+            Ok(()) => self.channel.send(()).unwrap(),
+            Err(e) => error!("Failed to enqueue tasks: {:?}", e),
+        }
+    }
+    /// Enqueue task unless the same task is already pending.
+    pub async fn enqueue_unique(&self, item: JobTask) {
+        let task = sqlx::types::Json(&item);
+        let res = query!(
+            "SELECT COUNT(*) as count FROM job_queue WHERE state = ? AND task = ?",
+            JobState::Pending,
+            task
+        )
+        .fetch_one(&*POOL)
+        .await;
+        match res {
+            Ok(row) if row.count > 0 => {
+                info!("Already queued: {:?}", item);
+            }
+            _ => self.enqueue(item).await,
+        }
+    }
+    /// Take the last queued task and mark it as running.
+    pub async fn dequeue(&self) -> Option<(i64, JobTask)> {
+        let res = query!(
+            r#"
+                UPDATE job_queue
+                SET state = ?, updated_at = CURRENT_TIMESTAMP
+                WHERE id = (SELECT id FROM job_queue WHERE state = ? ORDER BY id DESC LIMIT 1)
+                RETURNING id as "id!: i64", task as "task: sqlx::types::Json<JobTask>""#,
+            JobState::Running,
+            JobState::Pending
+        )
+        .fetch_optional(&*POOL)
+        .await;
+        match res {
+            Ok(row) => row.map(|row| (row.id, row.task.0)),
+            Err(e) => {
+                error!("Failed to dequeue task: {:?}", e);
+                None
+            }
+        }
+    }
+    pub async fn finish(&self, id: i64, success: bool) {
+        let state = if success {
+            JobState::Done
+        } else {
+            JobState::Failed
+        };
+        let res = query!(
+            "UPDATE job_queue SET state = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?",
+            state,
+            id
+        )
+        .execute(&*POOL)
+        .await;
+        if let Err(e) = res {
+            error!("Failed to update task state: {:?}", e);
+        }
     }
-    pub fn clear(&self) {
-        self.queue.lock().unwrap().clear();
+    /// Remove all pending tasks.
+    pub async fn clear(&self) {
+        let res = query!("DELETE FROM job_queue WHERE state = ?", JobState::Pending)
+            .execute(&*POOL)
+            .await;
+        if let Err(e) = res {
+            error!("Failed to clear queue: {:?}", e);
+        }
+    }
+    pub async fn info(&self) -> Result<QueueInfo, sqlx::Error> {
+        let tasks = query!(
+            r#"SELECT task as "task: sqlx::types::Json<JobTask>" FROM job_queue WHERE state = ? ORDER BY id"#,
+            JobState::Pending
+        )
+        .fetch_all(&*POOL)
+        .await?
+        .into_iter()
+        .map(|row| row.task.0)
+        .collect();
+        let running_count = query!(
+            "SELECT COUNT(*) as count FROM job_queue WHERE state = ?",
+            JobState::Running
+        )
+        .fetch_one(&*POOL)
+        .await?
+        .count;
+        Ok(QueueInfo {
+            tasks,
+            running_count: running_count as usize,
+        })
     }
 }
 
@@ -87,63 +255,155 @@ impl Queue {
 pub async fn start_job(mut job_receiver: JobReceiver) {
     let (queue, mut receiver) = Queue::new();
     let queue = Arc::new(queue);
+    if let Err(e) = queue.restore().await {
+        error!("Failed to restore queue: {:?}", e);
+    }
+
+    tokio::spawn(acoustid_submit_job::acoustid_submit_job());
 
-    let semaphore = Arc::new(tokio::sync::Semaphore::new(1));
+    let (source_dir, target_dir) = {
+        let config = CONFIG.read();
+        (config.source_dir.clone(), config.target_dir.clone())
+    };
+    for dir in [source_dir, target_dir] {
+        utils::remove_temp_files(Path::new(&dir)).await;
+    }
+
+    let concurrency = CONFIG.read().concurrency.max(1);
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(concurrency));
 
     {
         let queue = queue.clone();
-        let semaphore = semaphore.clone();
         tokio::spawn(async move {
             while let Some(job) = job_receiver.recv().await {
                 match job {
-                    JobCommand::Scan { path, retry_count } => {
-                        queue.enqueue(JobTask::Scan { path, retry_count });
-                    }
-                    JobCommand::ScanAll => {
-                        let source_dir = CONFIG.read().source_dir.clone();
-                        for item in walkdir::WalkDir::new(source_dir).into_iter().flatten() {
-                            if item.file_type().is_file() {
-                                queue.enqueue(JobTask::Scan {
-                                    path: item.path().to_path_buf(),
-                                    retry_count: 0,
-                                });
+                    JobCommand::Scan {
+                        path,
+                        retry_count,
+                        dry_run,
+                    } => {
+                        let (album_mode, dry_run) = {
+                            let config = CONFIG.read();
+                            (config.album_mode, dry_run.unwrap_or(config.dry_run))
+                        };
+                        if album_mode {
+                            let dir = if path.is_dir() {
+                                Some(path)
+                            } else {
+                                path.parent().map(|p| p.to_path_buf())
+                            };
+                            if let Some(dir) = dir {
+                                queue
+                                    .enqueue_unique(JobTask::ScanAlbum { dir, dry_run })
+                                    .await;
                             }
+                        } else {
+                            queue
+                                .enqueue(JobTask::Scan {
+                                    path,
+                                    retry_count,
+                                    dry_run,
+                                })
+                                .await;
                         }
                     }
-                    JobCommand::ClearQueue => {
-                        queue.clear();
+                    JobCommand::ScanAll { dry_run } => {
+                        let (source_dir, album_mode, dry_run) = {
+                            let config = CONFIG.read();
+                            (
+                                config.source_dir.clone(),
+                                config.album_mode,
+                                dry_run.unwrap_or(config.dry_run),
+                            )
+                        };
+                        let files = walkdir::WalkDir::new(source_dir)
+                            .into_iter()
+                            .flatten()
+                            .filter(|item| item.file_type().is_file())
+                            .map(|item| item.path().to_path_buf());
+                        let tasks = if album_mode {
+                            files
+                                .filter_map(|path| path.parent().map(|p| p.to_path_buf()))
+                                .collect::<BTreeSet<_>>()
+                                .into_iter()
+                                .map(|dir| JobTask::ScanAlbum { dir, dry_run })
+                                .collect()
+                        } else {
+                            files
+                                .map(|path| JobTask::Scan {
+                                    path,
+                                    retry_count: 0,
+                                    dry_run,
+                                })
+                                .collect()
+                        };
+                        queue.enqueue_all(tasks).await;
+                    }
+                    JobCommand::ApplyProposals { log_ids } => {
+                        let tasks = log_ids
+                            .into_iter()
+                            .map(|log_id| JobTask::ApplyProposal { log_id })
+                            .collect();
+                        queue.enqueue_all(tasks).await;
                     }
-                    JobCommand::GetQueueInfo { sender } => {
-                        sender
-                            .send(QueueInfo {
-                                tasks: queue.queue.lock().unwrap().clone(),
-                                running_count: 1 - semaphore.available_permits(),
+                    JobCommand::Revert { log_id } => {
+                        queue.enqueue(JobTask::Revert { log_id }).await;
+                    }
+                    JobCommand::RefreshLibrary => {
+                        let (target_dir, allowed_extensions) = {
+                            let config = CONFIG.read();
+                            (config.target_dir.clone(), config.allowed_extensions.clone())
+                        };
+                        let tasks = walkdir::WalkDir::new(target_dir)
+                            .into_iter()
+                            .flatten()
+                            .filter(|item| item.file_type().is_file())
+                            .map(|item| item.path().to_path_buf())
+                            .filter(|path| {
+                                path.extension()
+                                    .and_then(|ext| ext.to_str())
+                                    .is_some_and(|ext| allowed_extensions.iter().any(|e| e == ext))
                             })
-                            .unwrap();
+                            .map(|path| JobTask::Refresh { path })
+                            .collect();
+                        queue.enqueue_all(tasks).await;
+                    }
+                    JobCommand::ClearQueue => {
+                        queue.clear().await;
                     }
+                    JobCommand::GetQueueInfo { sender } => match queue.info().await {
+                        Ok(info) => {
+                            let _ = sender.send(info);
+                        }
+                        Err(e) => error!("Failed to get queue info: {:?}", e),
+                    },
                     JobCommand::Fix {
                         target_path,
                         release_id,
                         recording_id,
                     } => {
-                        queue.enqueue(JobTask::Fix {
-                            path: target_path,
-                            release_id,
-                            recording_id,
-                            copy_to_target: false,
-                        });
+                        queue
+                            .enqueue(JobTask::Fix {
+                                path: target_path,
+                                release_id,
+                                recording_id,
+                                copy_to_target: false,
+                            })
+                            .await;
                     }
                     JobCommand::FixFailed {
                         source_path,
                         release_id,
                         recording_id,
                     } => {
-                        queue.enqueue(JobTask::Fix {
-                            path: source_path,
-                            release_id,
-                            recording_id,
-                            copy_to_target: true,
-                        });
+                        queue
+                            .enqueue(JobTask::Fix {
+                                path: source_path,
+                                release_id,
+                                recording_id,
+                                copy_to_target: true,
+                            })
+                            .await;
                     }
                 }
             }
@@ -152,29 +412,39 @@ pub async fn start_job(mut job_receiver: JobReceiver) {
 
     loop {
         if receiver.recv().await.is_some() {
-            while let Some(item) = queue.dequeue() {
-                let semaphore = semaphore.clone();
+            loop {
                 let permit = semaphore.clone().acquire_owned().await.unwrap();
-                match item {
-                    JobTask::Scan { path, retry_count } => {
-                        let q2 = queue.clone();
-                        tokio::spawn(async move {
-                            let _permit = permit;
-                            scan_job::scan_job(&path, q2, retry_count).await;
-                        });
-                    }
-                    JobTask::Fix {
-                        path,
-                        release_id,
-                        recording_id,
-                        copy_to_target,
-                    } => {
-                        tokio::spawn(async move {
-                            let _permit = permit;
-                            fix_job::fix_job(&path, release_id, recording_id, copy_to_target).await;
-                        });
-                    }
-                }
+                let Some((id, item)) = queue.dequeue().await else {
+                    break;
+                };
+                let queue = queue.clone();
+                tokio::spawn(async move {
+                    let _permit = permit;
+                    let success = match item {
+                        JobTask::Scan {
+                            path,
+                            retry_count,
+                            dry_run,
+                        } => scan_job::scan_job(&path, queue.clone(), retry_count, dry_run).await,
+                        JobTask::ScanAlbum { dir, dry_run } => {
+                            scan_job::scan_album_job(&dir, queue.clone(), dry_run).await
+                        }
+                        JobTask::ApplyProposal { log_id } => {
+                            scan_job::apply_job(log_id.into()).await
+                        }
+                        JobTask::Revert { log_id } => revert_job::revert_job(log_id.into()).await,
+                        JobTask::Refresh { path } => refresh_job::refresh_job(&path).await,
+                        JobTask::Fix {
+                            path,
+                            release_id,
+                            recording_id,
+                            copy_to_target,
+                        } => {
+                            fix_job::fix_job(&path, release_id, recording_id, copy_to_target).await
+                        }
+                    };
+                    queue.finish(id, success).await;
+                });
             }
         }
     }
@@ -0,0 +1,120 @@
+use std::path::{Path, PathBuf};
+
+use eyre::bail;
+use sqlx::query;
+use tracing::{error, info};
+
+use crate::{
+    config::CONFIG,
+    interface::{log::LogType, metadata::Metadata},
+    POOL,
+};
+
+use super::utils::{
+    get_save_path_from_metadata, library, move_file_with_tag, read_tag_or_default, refresh_tag,
+    remove_empty_dirs,
+};
+
+/// Re-sync tags of the file in `target_dir` with current data of musicbrainz ids in the file.
+/// The file is moved if the path computed from new metadata is changed.
+#[tracing::instrument]
+pub async fn refresh_job(path: &Path) -> bool {
+    let res = refresh_job_inner(path).await;
+    match res {
+        Ok(Some(res)) => {
+            info!("Refreshed: {}", res.target_path.display());
+            let old_metadata = serde_json::to_string(&res.old_metadata).unwrap();
+            let new_metadata = serde_json::to_string(&res.new_metadata).unwrap();
+            let source_path = path.to_string_lossy();
+            let target_path = res.target_path.to_string_lossy();
+            let res = query!(
+                "INSERT INTO log (success, type, message, old_metadata, new_metadata, source_path, target_path) VALUES (?,?,?,?,?,?,?)",
+                true,
+                LogType::Refresh,
+                "",
+                old_metadata,
+                new_metadata,
+                source_path,
+                target_path,
+            )
+            .execute(&*POOL)
+            .await;
+            if let Err(err) = res {
+                error!("Failed to insert log: {:?}", err);
+            }
+            true
+        }
+        Ok(None) => true,
+        Err(e) => {
+            error!("Failed to refresh: {:?}", e);
+            let msg = format!("{:?}", e);
+            let source = path.to_string_lossy();
+            let res = query!(
+                "INSERT INTO log (success, type, message, source_path) VALUES (?,?,?,?)",
+                false,
+                LogType::Refresh,
+                msg,
+                source,
+            )
+            .execute(&*POOL)
+            .await;
+            if let Err(err) = res {
+                error!("Failed to insert log: {:?}", err);
+            }
+            false
+        }
+    }
+}
+
+struct RefreshJobRes {
+    old_metadata: Metadata,
+    new_metadata: Metadata,
+    target_path: PathBuf,
+}
+
+/// Returns None if the file has no musicbrainz ids or nothing was changed.
+async fn refresh_job_inner(path: &Path) -> eyre::Result<Option<RefreshJobRes>> {
+    let mut tag = read_tag_or_default(path)?;
+    let old_metadata = Metadata::from_tag(&tag);
+    if old_metadata.musicbrainz_release_id.is_none()
+        || old_metadata.musicbrainz_recording_id.is_none()
+    {
+        info!("Skipping: {} (no musicbrainz ids)", path.display());
+        return Ok(None);
+    }
+
+    let new_metadata = refresh_tag(&mut tag, &old_metadata).await?;
+
+    let (target_dir, new_path) = {
+        let config = CONFIG.read();
+        let target_dir = PathBuf::from(&config.target_dir);
+        let new_path =
+            get_save_path_from_metadata(path, &target_dir, &config.path_template, &new_metadata)?;
+        (target_dir, new_path)
+    };
+
+    if new_metadata == old_metadata && new_path == path {
+        info!("Up to date: {}", path.display());
+        return Ok(None);
+    }
+
+    if new_path != path && tokio::fs::try_exists(&new_path).await? {
+        bail!("File already exists: {}", new_path.display());
+    }
+
+    tokio::fs::create_dir_all(new_path.parent().unwrap()).await?;
+    move_file_with_tag(path, &new_path, &tag).await?;
+    if new_path != path {
+        library::remove(path).await;
+        if let Some(parent) = path.parent() {
+            remove_empty_dirs(parent, &target_dir).await;
+        }
+    }
+    library::record(&new_path, &new_metadata).await;
+
+    Ok(Some(RefreshJobRes {
+        old_metadata,
+        new_metadata,
+        target_path: new_path,
+    }))
+}
@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+
+use eyre::{bail, eyre};
+use sqlx::query;
+use tracing::{error, info};
+
+use crate::{
+    config::CONFIG,
+    interface::{
+        log::LogType,
+        metadata::{remove_metadata, write_metadata, Metadata},
+    },
+    watcher::mark_written,
+    POOL,
+};
+
+use super::utils::{
+    library, move_file_with_tag, read_tag_or_default, remove_empty_dirs, temp_path_for,
+};
+
+/// Revert scan or fix recorded in the log.
+#[tracing::instrument]
+pub async fn revert_job(log_id: i64) -> bool {
+    let res = revert_job_inner(log_id).await;
+    match res {
+        Ok(res) => {
+            info!("Reverted: {}", res.restored_path.display());
+            let old_metadata = serde_json::to_string(&res.old_metadata).unwrap();
+            let new_metadata = serde_json::to_string(&res.new_metadata).unwrap();
+            let message = format!("Reverted log #{}", log_id);
+            let source_path = res.reverted_path.to_string_lossy();
+            let target_path = res.restored_path.to_string_lossy();
+            let res = query!(
+                "INSERT INTO log (success, type, message, old_metadata, new_metadata, source_path, target_path) VALUES (?,?,?,?,?,?,?)",
+                true,
+                LogType::Revert,
+                message,
+                old_metadata,
+                new_metadata,
+                source_path,
+                target_path,
+            )
+            .execute(&*POOL)
+            .await;
+            if let Err(err) = res {
+                error!("Failed to insert log: {:?}", err);
+            }
+            true
+        }
+        Err(err) => {
+            error!("Failed to revert: {:?}", err);
+            let err = format!("{:?}", err);
+            let res = query!(
+                "INSERT INTO log (success, type, message, source_path) SELECT ?, ?, ?, COALESCE(target_path, source_path) FROM log WHERE id = ?",
+                false,
+                LogType::Revert,
+                err,
+                log_id
+            )
+            .execute(&*POOL)
+            .await;
+            if let Err(err) = res {
+                error!("Failed to insert log: {:?}", err);
+            }
+            false
+        }
+    }
+}
+
+struct RevertJobRes {
+    /// Metadata of the file before revert.
+    old_metadata: Option<Metadata>,
+    /// Metadata of the file after revert.
+    new_metadata: Option<Metadata>,
+    reverted_path: PathBuf,
+    restored_path: PathBuf,
+}
+
+async fn revert_job_inner(log_id: i64) -> eyre::Result<RevertJobRes> {
+    let log = query!(
+        r#"
+            SELECT
+                source_path,
+                target_path,
+                old_metadata as "old_metadata?: sqlx::types::Json<Metadata>",
+                new_metadata as "new_metadata?: sqlx::types::Json<Metadata>"
+            FROM log
+            WHERE id = ? AND type IN (?, ?) AND success = true"#,
+        log_id,
+        LogType::Scan,
+        LogType::Fix
+    )
+    .fetch_optional(&*POOL)
+    .await?
+    .ok_or_else(|| eyre!("Revertable log not found: {}", log_id))?;
+
+    let source_path = PathBuf::from(log.source_path);
+    let saved_path = log
+        .target_path
+        .ok_or_else(|| eyre!("Log has no target path"))?;
+
+    // The saved file may have been replaced by a later scan according to the conflict policy.
+    let replacement = query!(
+        "SELECT target_path FROM log WHERE type = ? AND success = true AND source_path = ? AND id > ? ORDER BY id LIMIT 1",
+        LogType::Replace,
+        saved_path,
+        log_id
+    )
+    .fetch_optional(&*POOL)
+    .await?;
+    let target_path = match replacement {
+        Some(replacement) => {
+            let archived_path = replacement.target_path.ok_or_else(|| {
+                eyre!(
+                    "File was replaced by a later scan and deleted: {}",
+                    saved_path
+                )
+            })?;
+            info!(
+                "{} was replaced, reverting archived file: {}",
+                saved_path, archived_path
+            );
+            PathBuf::from(archived_path)
+        }
+        None => PathBuf::from(saved_path),
+    };
+    if !tokio::fs::try_exists(&target_path).await? {
+        bail!("Target file not found: {}", target_path.display());
+    }
+
+    let mut tag = read_tag_or_default(&target_path)?;
+    if let Some(new_metadata) = &log.new_metadata {
+        let current = Metadata::from_tag(&tag);
+        if current.musicbrainz_recording_id != new_metadata.musicbrainz_recording_id
+            || current.musicbrainz_release_id != new_metadata.musicbrainz_release_id
+        {
+            bail!(
+                "File was changed after it was saved: {}",
+                target_path.display()
+            );
+        }
+    }
+
+    if source_path != target_path && tokio::fs::try_exists(&source_path).await? {
+        // Original file is still there, so only the copy has to be removed.
+        tokio::fs::remove_file(&target_path).await?;
+    } else {
+        let old_metadata = log
+            .old_metadata
+            .clone()
+            .ok_or_else(|| eyre!("Log has no original metadata"))?
+            .0;
+        // Restored file must not be scanned again by watcher.
+        mark_written(&target_path);
+        mark_written(&source_path);
+        mark_written(&temp_path_for(&source_path));
+        remove_metadata(&mut tag);
+        write_metadata(&mut tag, old_metadata);
+
+        tokio::fs::create_dir_all(source_path.parent().unwrap()).await?;
+        move_file_with_tag(&target_path, &source_path, &tag).await?;
+        mark_written(&source_path);
+    }
+    library::remove(&target_path).await;
+
+    let target_dir = CONFIG.read().target_dir.clone();
+    if let Some(parent) = target_path.parent() {
+        remove_empty_dirs(parent, Path::new(&target_dir)).await;
+    }
+
+    Ok(RevertJobRes {
+        old_metadata: log.new_metadata.map(|x| x.0),
+        new_metadata: log.old_metadata.map(|x| x.0),
+        reverted_path: target_path,
+        restored_path: source_path,
+    })
+}
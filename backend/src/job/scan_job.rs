@@ -5,77 +5,292 @@ use tracing::{error, info, warn};
 
 use crate::{
     config::CONFIG,
-    interface::log::LogType,
-    job::{scan_job::scan_and_copy::scan_and_copy, JobTask},
+    interface::{candidate::Candidate, log::LogType, metadata::Metadata},
+    job::{
+        scan_job::scan_and_copy::{
+            apply_proposal, copy_album_file, scan_album, scan_and_copy, NoMatchError,
+            ScanSuccessLog, ScannerInfo,
+        },
+        JobTask,
+    },
     POOL,
 };
 
 mod scan_and_copy;
 
+pub(super) use self::scan_and_copy::save_fixed;
+
+/// Returns true if the file was scanned successfully or skipped.
 #[tracing::instrument(skip(queue))]
-pub async fn scan_job(path: &Path, queue: Arc<crate::job::Queue>, retry_count: u8) {
+pub async fn scan_job(
+    path: &Path,
+    queue: Arc<crate::job::Queue>,
+    retry_count: u8,
+    dry_run: bool,
+) -> bool {
+    let log_type = if dry_run {
+        LogType::Proposal
+    } else {
+        LogType::Scan
+    };
     if let Some(ext) = path.extension().and_then(|s| s.to_str()) {
         if CONFIG.read().allowed_extensions.iter().any(|e| e == ext) {
-            let res = scan_and_copy(path).await;
-            match res {
+            let res = scan_and_copy(path, dry_run).await;
+            return match res {
                 Ok(res) => {
                     info!("Finished scanning: {}", path.display());
-                    let old_metadata = serde_json::to_string(&res.old_metadata).unwrap();
-                    let new_metadata = serde_json::to_string(&res.new_metadata).unwrap();
-                    let source_path = path.to_string_lossy();
-                    let target_path = res.target_path.to_string_lossy();
-                    let (acoustid_score, message) = match res.scanner_info {
-                        scan_and_copy::ScannerInfo::AcoustId { score } => {
-                            (Some(score), "Scanner: AcoustId")
-                        }
-                        scan_and_copy::ScannerInfo::MusicbrainzSearch => {
-                            (None, "Scanner: MusicBrainz Search")
-                        }
-                        scan_and_copy::ScannerInfo::Skip => (None, "Scanner: Skip"),
-                    };
-                    let res = query!(
-                        "INSERT INTO log (success, type, message, old_metadata, new_metadata, source_path, target_path, acoustid_score, retry_count) VALUES (?,?,?,?,?,?,?,?,?)",
-                        true,
-                        LogType::Scan,
-                        message,
-                        old_metadata,
-                        new_metadata,
-                        source_path,
-                        target_path,
-                        acoustid_score,
-                        retry_count
-                    ).execute(&*POOL).await;
-                    if let Err(err) = res {
-                        error!("Failed to insert log: {:?}", err);
-                    }
+                    insert_success_log(path, res, log_type, retry_count).await;
+                    true
                 }
                 Err(err) => {
                     if retry_count < 1 {
                         warn!("Failed to scan. Retrying...: {:?}", err);
-                        queue.enqueue(JobTask::Scan {
-                            path: path.to_path_buf(),
-                            retry_count: retry_count + 1,
-                        });
+                        queue
+                            .enqueue(JobTask::Scan {
+                                path: path.to_path_buf(),
+                                retry_count: retry_count + 1,
+                                dry_run,
+                            })
+                            .await;
                     } else {
                         error!("Failed to scan: {:?}", err);
-                        let err = format!("{:?}", err);
-                        let path = path.to_string_lossy();
-                        query!(
-                            "INSERT INTO log (success, type, message, source_path, retry_count) VALUES (?,?,?,?,?)",
-                            false,
-                            LogType::Scan,
-                            err,
-                            path,
-                            retry_count
-                        )
-                        .execute(&*POOL)
-                        .await
-                        .unwrap();
+                        insert_failure_log(path, &err, log_type, retry_count).await;
                     }
+                    false
                 }
-            }
-            return;
+            };
         }
     }
     info!("Skipping: {} (not allowed extension)", path.display());
+    true
+}
+
+/// Scan all files directly under the directory as one release.
+/// Files which couldn't be matched to the release are queued to be scanned separately.
+#[tracing::instrument(skip(queue))]
+pub async fn scan_album_job(dir: &Path, queue: Arc<crate::job::Queue>, dry_run: bool) -> bool {
+    let log_type = if dry_run {
+        LogType::Proposal
+    } else {
+        LogType::Scan
+    };
+    let allowed_extensions = CONFIG.read().allowed_extensions.clone();
+    let mut paths = walkdir::WalkDir::new(dir)
+        .max_depth(1)
+        .into_iter()
+        .flatten()
+        .filter(|item| item.file_type().is_file())
+        .map(|item| item.path().to_path_buf())
+        .filter(|path| {
+            path.extension()
+                .and_then(|s| s.to_str())
+                .is_some_and(|ext| allowed_extensions.iter().any(|e| e == ext))
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    if paths.is_empty() {
+        info!("Skipping: {} (no files to scan)", dir.display());
+        return true;
+    }
+
+    let (success, matched, unmatched) = match scan_album(&paths, dry_run).await {
+        Ok(res) => (true, res.matched, res.unmatched),
+        Err(err) => {
+            warn!(
+                "Failed to scan album. Scanning each file separately...: {:?}",
+                err
+            );
+            (false, vec![], paths)
+        }
+    };
+
+    for file in matched {
+        let path = file.path.clone();
+        match copy_album_file(file, dry_run).await {
+            Ok(res) => {
+                info!("Finished scanning: {}", path.display());
+                insert_success_log(&path, res, log_type, 0).await;
+            }
+            Err(err) => {
+                error!("Failed to scan: {:?}", err);
+                insert_failure_log(&path, &err, log_type, 0).await;
+            }
+        }
+    }
+    if !unmatched.is_empty() {
+        queue
+            .enqueue_all(
+                unmatched
+                    .into_iter()
+                    .map(|path| JobTask::Scan {
+                        path,
+                        retry_count: 0,
+                        dry_run,
+                    })
+                    .collect(),
+            )
+            .await;
+    }
+
+    success
+}
+
+async fn insert_success_log(path: &Path, res: ScanSuccessLog, log_type: LogType, retry_count: u8) {
+    let candidates = res.candidates;
+    let old_metadata = serde_json::to_string(&res.old_metadata).unwrap();
+    let new_metadata = serde_json::to_string(&res.new_metadata).unwrap();
+    let source_path = path.to_string_lossy();
+    let target_path = res.target_path.to_string_lossy();
+    let score_breakdown = res.score.map(sqlx::types::Json);
+    let (acoustid_score, message) = match res.scanner_info {
+        ScannerInfo::AcoustId { score } => (Some(score), "Scanner: AcoustId"),
+        ScannerInfo::MusicbrainzSearch => (None, "Scanner: MusicBrainz Search"),
+        ScannerInfo::Path => (None, "Scanner: Path"),
+        ScannerInfo::Album { score } => (score, "Scanner: Album"),
+        ScannerInfo::Refresh => (None, "Scanner: Refresh"),
+        ScannerInfo::Skip => (None, "Scanner: Skip"),
+    };
+    let res = query!(
+        "INSERT INTO log (success, type, message, old_metadata, new_metadata, source_path, target_path, acoustid_score, retry_count, score_breakdown) VALUES (?,?,?,?,?,?,?,?,?,?)",
+        true,
+        log_type,
+        message,
+        old_metadata,
+        new_metadata,
+        source_path,
+        target_path,
+        acoustid_score,
+        retry_count,
+        score_breakdown
+    ).execute(&*POOL).await;
+    match res {
+        Ok(row) => insert_candidates(row.last_insert_rowid(), &candidates).await,
+        Err(err) => error!("Failed to insert log: {:?}", err),
+    }
+}
+
+async fn insert_failure_log(path: &Path, err: &eyre::Report, log_type: LogType, retry_count: u8) {
+    let candidates = err
+        .downcast_ref::<NoMatchError>()
+        .map(|e| e.candidates.clone())
+        .unwrap_or_default();
+    let err = format!("{:?}", err);
+    let path = path.to_string_lossy();
+    let res = query!(
+        "INSERT INTO log (success, type, message, source_path, retry_count) VALUES (?,?,?,?,?)",
+        false,
+        log_type,
+        err,
+        path,
+        retry_count
+    )
+    .execute(&*POOL)
+    .await;
+    match res {
+        Ok(row) => insert_candidates(row.last_insert_rowid(), &candidates).await,
+        Err(err) => error!("Failed to insert log: {:?}", err),
+    }
+}
+
+async fn insert_candidates(log_id: i64, candidates: &[Candidate]) {
+    let res = async {
+        let mut tx = POOL.begin().await?;
+        for (rank, candidate) in candidates.iter().enumerate() {
+            let rank = rank as i64;
+            let score_breakdown = sqlx::types::Json(&candidate.score);
+            query!(
+                "INSERT INTO scan_candidate (log_id, rank, recording_id, recording_title, release_id, release_title, artist, score, score_breakdown) VALUES (?,?,?,?,?,?,?,?,?)",
+                log_id,
+                rank,
+                candidate.recording_id,
+                candidate.recording_title,
+                candidate.release_id,
+                candidate.release_title,
+                candidate.artist,
+                candidate.score.total,
+                score_breakdown
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+    .await;
+    if let Err(err) = res {
+        error!("Failed to insert candidates: {:?}", err);
+    }
+}
+
+/// Apply proposal created by dry-run scan.
+#[tracing::instrument]
+pub async fn apply_job(log_id: i64) -> bool {
+    let res = async {
+        let proposal = query!(
+            r#"
+                SELECT
+                    source_path,
+                    target_path,
+                    new_metadata as "new_metadata?: sqlx::types::Json<Metadata>"
+                FROM log
+                WHERE id = ? AND type = ? AND success = true"#,
+            log_id,
+            LogType::Proposal
+        )
+        .fetch_optional(&*POOL)
+        .await?
+        .ok_or_else(|| eyre::eyre!("Proposal not found: {}", log_id))?;
+
+        let target_path = proposal
+            .target_path
+            .ok_or_else(|| eyre::eyre!("Proposal has no target path"))?;
+        let new_metadata = proposal
+            .new_metadata
+            .ok_or_else(|| eyre::eyre!("Proposal has no metadata"))?;
+
+        let target_path = apply_proposal(
+            Path::new(&proposal.source_path),
+            Path::new(&target_path),
+            new_metadata.0,
+        )
+        .await?;
+
+        Ok::<_, eyre::Report>((proposal.source_path, target_path))
+    }
+    .await;
+
+    match res {
+        Ok((source_path, target_path)) => {
+            info!("Applied proposal: {}", source_path);
+            let target_path = target_path.to_string_lossy();
+            let res = query!(
+                "UPDATE log SET type = ?, target_path = ?, created_at = CURRENT_TIMESTAMP WHERE id = ?",
+                LogType::Scan,
+                target_path,
+                log_id
+            )
+            .execute(&*POOL)
+            .await;
+            if let Err(err) = res {
+                error!("Failed to update log: {:?}", err);
+            }
+            true
+        }
+        Err(err) => {
+            error!("Failed to apply proposal: {:?}", err);
+            let err = format!("{:?}", err);
+            let res = query!(
+                "INSERT INTO log (success, type, message, source_path) SELECT ?, ?, ?, source_path FROM log WHERE id = ?",
+                false,
+                LogType::Scan,
+                err,
+                log_id
+            )
+            .execute(&*POOL)
+            .await;
+            if let Err(err) = res {
+                error!("Failed to insert log: {:?}", err);
+            }
+            false
+        }
+    }
 }
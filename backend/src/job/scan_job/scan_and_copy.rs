@@ -1,14 +1,19 @@
 use std::path::{Path, PathBuf};
 
 use eyre::Context;
-use tracing::warn;
+use lofty::tag::Tag;
 
 use crate::config::CONFIG;
-use crate::interface::metadata::Metadata;
-use crate::job::utils::get_save_path_from_metadata;
+use crate::interface::candidate::{Candidate, ScoreBreakdown};
+use crate::interface::metadata::{write_metadata, Metadata};
+use crate::job::utils::{
+    get_in_place_path_from_metadata, get_save_path_from_metadata, library, read_tag_or_default,
+    CoverArt,
+};
 
-use self::scan::ScanRes;
+use self::scan::{AlbumScanRes, ScanRes};
 
+mod conflict;
 mod save;
 mod scan;
 mod utils;
@@ -18,40 +23,283 @@ pub struct ScanSuccessLog {
     pub new_metadata: Metadata,
     pub scanner_info: ScannerInfo,
     pub target_path: PathBuf,
+    pub candidates: Vec<Candidate>,
+    pub score: Option<ScoreBreakdown>,
 }
+#[derive(Clone)]
 pub(crate) enum ScannerInfo {
-    AcoustId { score: f64 },
+    AcoustId {
+        score: f64,
+    },
     MusicbrainzSearch,
+    /// Searched with fields extracted from the path.
+    Path,
+    /// Identified as a part of the release in album mode.
+    Album {
+        score: Option<f64>,
+    },
+    /// Tagged with musicbrainz ids already in the file.
+    Refresh,
     Skip,
 }
 
+/// No candidate had score higher than threshold.
+/// Candidates are kept so that user can pick one manually.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to find best match")]
+pub struct NoMatchError {
+    pub candidates: Vec<Candidate>,
+}
+
+pub struct AlbumScan {
+    /// Files matched to the release. Each of them should be copied with `copy_album_file` and logged
+    /// before the next one, so that duplicates in the album are detected.
+    pub matched: Vec<AlbumFileMatch>,
+    /// Files which were not matched to the release and should be scanned separately.
+    pub unmatched: Vec<PathBuf>,
+}
+
+pub struct AlbumFileMatch {
+    pub path: PathBuf,
+    scan_res: ScanRes,
+}
+
+/// Scan file and copy it to target dir.
+/// If `dry_run` is true, only the target path is calculated and files are not touched.
 #[tracing::instrument]
-pub(super) async fn scan_and_copy(path: &Path) -> eyre::Result<ScanSuccessLog> {
+pub(super) async fn scan_and_copy(path: &Path, dry_run: bool) -> eyre::Result<ScanSuccessLog> {
+    let scan_res = scan::scan(path, dry_run).await.wrap_err("Failed to scan")?;
+    copy(path, scan_res, dry_run).await
+}
+
+/// Scan files in the same directory as one release.
+#[tracing::instrument]
+pub(super) async fn scan_album(paths: &[PathBuf], dry_run: bool) -> eyre::Result<AlbumScan> {
+    let AlbumScanRes { matched, unmatched } = scan::scan_album(paths, dry_run)
+        .await
+        .wrap_err("Failed to scan album")?;
+
+    Ok(AlbumScan {
+        matched: matched
+            .into_iter()
+            .map(|(path, scan_res)| AlbumFileMatch { path, scan_res })
+            .collect(),
+        unmatched,
+    })
+}
+
+/// Copy a file matched in album scan to target dir.
+pub(super) async fn copy_album_file(
+    file: AlbumFileMatch,
+    dry_run: bool,
+) -> eyre::Result<ScanSuccessLog> {
+    copy(&file.path, file.scan_res, dry_run).await
+}
+
+async fn copy(path: &Path, scan_res: ScanRes, dry_run: bool) -> eyre::Result<ScanSuccessLog> {
     let ScanRes {
         old_metadata,
         new_metadata,
         new_tag,
         scanner_info,
-    } = scan::scan(path).await.wrap_err("Failed to scan")?;
+        candidates,
+        score,
+    } = scan_res;
 
-    let new_path =
-        get_save_path_from_metadata(path, Path::new(&CONFIG.read().target_dir), &new_metadata)?;
-
-    save::save_file(path, &new_path, new_tag)
-        .await
-        .wrap_err("Failed to save tag")?;
+    let in_place = {
+        let config = CONFIG.read();
+        config.in_place.is_enabled_for(path, &config.source_dir)
+    };
+    let new_path = save_path(path, &new_metadata, in_place)?;
 
-    if CONFIG.read().delete_original {
-        let res = tokio::fs::remove_file(path).await;
-        if let Err(e) = res {
-            warn!("Failed to delete original file: {}", e);
-        }
+    if dry_run {
+        let target_path = if in_place {
+            new_path
+        } else {
+            conflict::resolve_conflict(path, &new_path, &new_metadata)
+                .await?
+                .target_path
+        };
+        return Ok(ScanSuccessLog {
+            old_metadata,
+            new_metadata,
+            scanner_info,
+            target_path,
+            candidates,
+            score,
+        });
     }
 
+    let target_path = if in_place {
+        save_in_place(path, &new_path, new_tag, &new_metadata).await?;
+        new_path
+    } else {
+        let move_source = CONFIG.read().delete_original;
+        save_to_target(path, &new_path, new_tag, &new_metadata, move_source).await?
+    };
+    write_folder_image(
+        CoverArt::from_metadata(&new_metadata).as_ref(),
+        &target_path,
+    )
+    .await;
+
     Ok(ScanSuccessLog {
         old_metadata,
         new_metadata,
         scanner_info,
-        target_path: new_path,
+        target_path,
+        candidates,
+        score,
     })
 }
+
+/// Apply the result of dry-run scan. Returns the path where the file was saved.
+#[tracing::instrument(skip(new_metadata))]
+pub(super) async fn apply_proposal(
+    source_path: &Path,
+    target_path: &Path,
+    new_metadata: Metadata,
+) -> eyre::Result<PathBuf> {
+    let mut tag = read_tag_or_default(source_path)?;
+
+    let cover_art = CoverArt::from_metadata(&new_metadata);
+    if let Some(cover_art) = &cover_art {
+        cover_art.embed(&mut tag).await;
+    }
+
+    let in_place = {
+        let config = CONFIG.read();
+        config
+            .in_place
+            .is_enabled_for(source_path, &config.source_dir)
+    };
+    write_metadata(&mut tag, new_metadata.clone());
+    let target_path = if in_place {
+        save_in_place(source_path, target_path, tag, &new_metadata).await?;
+        target_path.to_path_buf()
+    } else {
+        // Files in target dir may have been changed since the proposal was made.
+        let move_source = CONFIG.read().delete_original;
+        save_to_target(source_path, target_path, tag, &new_metadata, move_source).await?
+    };
+    write_folder_image(cover_art.as_ref(), &target_path).await;
+
+    Ok(target_path)
+}
+
+/// Path where the file is saved. If `in_place` is true, the file stays in its directory.
+fn save_path(path: &Path, metadata: &Metadata, in_place: bool) -> eyre::Result<PathBuf> {
+    let config = CONFIG.read();
+    if in_place {
+        get_in_place_path_from_metadata(
+            path,
+            &config.path_template,
+            metadata,
+            config.in_place.rename,
+        )
+    } else {
+        get_save_path_from_metadata(
+            path,
+            Path::new(&config.target_dir),
+            &config.path_template,
+            metadata,
+        )
+    }
+}
+
+/// Save the file fixed with manually selected release and recording.
+/// If `in_place` is true, tag is written to the file in its directory. Otherwise, the file is
+/// saved to target dir according to the conflict policy, and moved if `keep_source` is false.
+pub(crate) async fn save_fixed(
+    source_path: &Path,
+    tag: Tag,
+    new_metadata: &Metadata,
+    in_place: bool,
+    keep_source: bool,
+) -> eyre::Result<PathBuf> {
+    let target_path = save_path(source_path, new_metadata, in_place)?;
+    if in_place {
+        save_in_place(source_path, &target_path, tag, new_metadata).await?;
+        return Ok(target_path);
+    }
+    save_to_target(source_path, &target_path, tag, new_metadata, !keep_source).await
+}
+
+/// Write tag to the file in its directory and record it in the library.
+async fn save_in_place(
+    source_path: &Path,
+    target_path: &Path,
+    tag: Tag,
+    metadata: &Metadata,
+) -> eyre::Result<()> {
+    save::save_in_place(source_path, target_path, tag)
+        .await
+        .wrap_err("Failed to save tag")?;
+    if source_path != target_path {
+        library::remove(source_path).await;
+    }
+    library::record(target_path, metadata).await;
+    Ok(())
+}
+
+/// Save the file to target dir according to the conflict policy and record it in the library.
+/// Returns the path where the file was saved.
+/// If `move_source` is false, the source file is copied.
+async fn save_to_target(
+    source_path: &Path,
+    target_path: &Path,
+    tag: Tag,
+    metadata: &Metadata,
+    move_source: bool,
+) -> eyre::Result<PathBuf> {
+    // Other jobs must not save the same file until this file is recorded.
+    let _lock = conflict::lock(target_path, metadata).await;
+    let resolved = conflict::resolve_conflict(source_path, target_path, metadata).await?;
+    save_replacing(
+        source_path,
+        &resolved.target_path,
+        tag,
+        resolved.replaced,
+        move_source,
+    )
+    .await?;
+    if move_source && source_path != resolved.target_path {
+        library::remove(source_path).await;
+    }
+    library::record(&resolved.target_path, metadata).await;
+    Ok(resolved.target_path)
+}
+
+/// Save cover art in the directory of the saved file.
+async fn write_folder_image(cover_art: Option<&CoverArt>, path: &Path) {
+    if let (Some(cover_art), Some(dir)) = (cover_art, path.parent()) {
+        cover_art.write_folder_image(dir).await;
+    }
+}
+
+/// Save the file to target dir and replace duplicates resolved by the conflict policy.
+/// If `move_source` is false, the source file is copied.
+async fn save_replacing(
+    source_path: &Path,
+    target_path: &Path,
+    tag: Tag,
+    replaced: Vec<PathBuf>,
+    move_source: bool,
+) -> eyre::Result<()> {
+    let replacement = conflict::Replacement::prepare(replaced).await?;
+    let res = if move_source {
+        save::move_file_with_tag(source_path, target_path, tag).await
+    } else {
+        save::save_file(source_path, target_path, tag).await
+    };
+    match res {
+        Ok(()) => {
+            replacement.finish(source_path, target_path).await;
+            Ok(())
+        }
+        Err(e) => {
+            replacement.rollback().await;
+            Err(e).wrap_err("Failed to save tag")
+        }
+    }
+}
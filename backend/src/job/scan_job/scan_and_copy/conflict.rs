@@ -0,0 +1,501 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use eyre::{eyre, Context};
+use lofty::{
+    config::ParseOptions,
+    file::{AudioFile, FileType, TaggedFileExt},
+    iff::wav::{WavFile, WavFormat},
+    mp4::{Mp4Codec, Mp4File},
+    read_from_path,
+    wavpack::WavPackFile,
+};
+use once_cell::sync::Lazy;
+use sqlx::query;
+use tokio::sync::OwnedMutexGuard;
+use tracing::{error, info, warn};
+
+use crate::{
+    config::{ConflictPolicy, CONFIG},
+    interface::{log::LogType, metadata::Metadata},
+    job::utils::{library, move_file, read_tag_or_default, remove_empty_dirs},
+    POOL,
+};
+
+/// Locks of files being saved, keyed by musicbrainz recording and release ids and by target path.
+static SAVE_LOCKS: Lazy<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Lock of a file being saved. It must be held from conflict resolution until the file is saved
+/// and recorded in the library.
+pub(super) struct SaveLock {
+    _guards: Vec<OwnedMutexGuard<()>>,
+}
+
+/// Wait until no other job is saving the same recording of the same release, or a file to the same path.
+pub(super) async fn lock(target_path: &Path, metadata: &Metadata) -> SaveLock {
+    let mut keys = vec![format!("path:{}", target_path.to_string_lossy())];
+    if let (Some(recording_id), Some(release_id)) = (
+        &metadata.musicbrainz_recording_id,
+        &metadata.musicbrainz_release_id,
+    ) {
+        keys.push(format!("id:{}/{}", release_id, recording_id));
+    }
+    // Locks are always taken in the same order to avoid deadlock.
+    keys.sort();
+
+    let locks = {
+        let mut locks = SAVE_LOCKS.lock().unwrap();
+        // Remove locks which no one holds or waits for.
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        keys.into_iter()
+            .map(|key| locks.entry(key).or_default().clone())
+            .collect::<Vec<_>>()
+    };
+    let mut guards = vec![];
+    for lock in locks {
+        guards.push(lock.lock_owned().await);
+    }
+    SaveLock { _guards: guards }
+}
+
+pub(super) struct Resolved {
+    /// Path to save the file.
+    pub target_path: PathBuf,
+    /// Duplicates replaced by the new file. May include the file at `target_path`.
+    pub replaced: Vec<PathBuf>,
+}
+
+/// Decide where to save the file according to `on_conflict` in config.
+/// Returns error if the file should not be saved.
+pub(super) async fn resolve_conflict(
+    source_path: &Path,
+    target_path: &Path,
+    metadata: &Metadata,
+) -> eyre::Result<Resolved> {
+    let duplicates = find_duplicates(source_path, target_path, metadata).await?;
+    let replace_all = || Resolved {
+        target_path: target_path.to_path_buf(),
+        replaced: duplicates.clone(),
+    };
+    let Some(first) = duplicates.first() else {
+        return Ok(replace_all());
+    };
+
+    let policy = CONFIG.read().conflict_policy();
+    match policy {
+        ConflictPolicy::Skip => Err(eyre!(
+            "File already exists! Skipping...: {}",
+            first.display()
+        )),
+        ConflictPolicy::Overwrite => Ok(replace_all()),
+        ConflictPolicy::KeepBoth => Ok(Resolved {
+            target_path: free_path(target_path).await?,
+            replaced: vec![],
+        }),
+        ConflictPolicy::KeepHigherQuality => {
+            let quality = Quality::read(source_path)?;
+            for duplicate in &duplicates {
+                let existing = Quality::read(duplicate)?;
+                if existing.rank() >= quality.rank() {
+                    return Err(eyre!(
+                        "File with the same or higher quality already exists! Skipping...: {}",
+                        duplicate.display()
+                    ));
+                }
+            }
+            Ok(replace_all())
+        }
+    }
+}
+
+struct ReplacedFile {
+    path: PathBuf,
+    metadata: Option<Metadata>,
+    quality: Option<Quality>,
+    /// Path in `replaced_dir` where the file was moved to.
+    archived_path: Option<PathBuf>,
+}
+
+/// Files in target dir replaced by a new file.
+pub(super) struct Replacement {
+    files: Vec<ReplacedFile>,
+}
+
+impl Replacement {
+    /// Read replaced files and move them to `replaced_dir` if it's set.
+    /// This must be called before the new file is saved, because the new file may overwrite one of them.
+    pub(super) async fn prepare(replaced: Vec<PathBuf>) -> eyre::Result<Self> {
+        let (target_dir, replaced_dir) = {
+            let config = CONFIG.read();
+            (
+                PathBuf::from(&config.target_dir),
+                config.replaced_dir.clone().map(PathBuf::from),
+            )
+        };
+
+        let mut replacement = Self { files: vec![] };
+        for path in replaced {
+            let metadata = read_tag_or_default(&path)
+                .ok()
+                .map(|tag| Metadata::from_tag(&tag));
+            let quality = Quality::read(&path).ok();
+            let archived_path = match &replaced_dir {
+                Some(replaced_dir) => {
+                    let relative = path
+                        .strip_prefix(&target_dir)
+                        .ok()
+                        .map(Path::to_path_buf)
+                        .or_else(|| path.file_name().map(PathBuf::from))
+                        .unwrap_or_default();
+                    let archived_path = free_path(&replaced_dir.join(relative)).await?;
+                    let res = async {
+                        tokio::fs::create_dir_all(archived_path.parent().unwrap()).await?;
+                        move_file(&path, &archived_path).await
+                    }
+                    .await;
+                    if let Err(e) = res {
+                        replacement.rollback().await;
+                        return Err(e).wrap_err("Failed to move replaced file");
+                    }
+                    Some(archived_path)
+                }
+                None => None,
+            };
+            replacement.files.push(ReplacedFile {
+                path,
+                metadata,
+                quality,
+                archived_path,
+            });
+        }
+        Ok(replacement)
+    }
+
+    /// Move back replaced files when the new file couldn't be saved.
+    pub(super) async fn rollback(self) {
+        for file in self.files {
+            if let Some(archived_path) = file.archived_path {
+                if let Err(e) = move_file(&archived_path, &file.path).await {
+                    warn!("Failed to restore replaced file: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Remove replaced files which were not moved and log each replacement.
+    pub(super) async fn finish(self, source_path: &Path, target_path: &Path) {
+        let target_dir = CONFIG.read().target_dir.clone();
+        let new_quality = Quality::read(target_path).ok();
+        for file in self.files {
+            if file.archived_path.is_none() && file.path != target_path {
+                if let Err(e) = tokio::fs::remove_file(&file.path).await {
+                    warn!("Failed to remove replaced file: {}", e);
+                }
+            }
+            if file.path != target_path {
+                library::remove(&file.path).await;
+                if let Some(parent) = file.path.parent() {
+                    remove_empty_dirs(parent, Path::new(&target_dir)).await;
+                }
+            }
+            info!(
+                "Replaced {} with {}",
+                file.path.display(),
+                source_path.display()
+            );
+
+            let message = format!(
+                "Replaced by {} ({} -> {})",
+                target_path.display(),
+                file.quality
+                    .map(|q| q.to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+                new_quality
+                    .as_ref()
+                    .map(|q| q.to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+            );
+            let old_metadata = file
+                .metadata
+                .map(|metadata| serde_json::to_string(&metadata).unwrap());
+            let replaced_path = file.path.to_string_lossy();
+            let archived_path = file
+                .archived_path
+                .as_ref()
+                .map(|path| path.to_string_lossy());
+            let res = query!(
+                "INSERT INTO log (success, type, message, old_metadata, source_path, target_path) VALUES (?,?,?,?,?,?)",
+                true,
+                LogType::Replace,
+                message,
+                old_metadata,
+                replaced_path,
+                archived_path,
+            )
+            .execute(&*POOL)
+            .await;
+            if let Err(err) = res {
+                error!("Failed to insert log: {:?}", err);
+            }
+        }
+    }
+}
+
+/// Find files which exist at the target path or have the same musicbrainz recording and release ids.
+/// Files with the same ids are looked up from the library and checked against their current tags.
+async fn find_duplicates(
+    source_path: &Path,
+    target_path: &Path,
+    metadata: &Metadata,
+) -> eyre::Result<Vec<PathBuf>> {
+    let mut duplicates = vec![];
+    if target_path != source_path && tokio::fs::try_exists(target_path).await? {
+        duplicates.push(target_path.to_path_buf());
+    }
+
+    let (Some(recording_id), Some(release_id)) = (
+        &metadata.musicbrainz_recording_id,
+        &metadata.musicbrainz_release_id,
+    ) else {
+        return Ok(duplicates);
+    };
+    let target_dir = CONFIG.read().target_dir.clone();
+    for path in library::find(recording_id, release_id).await? {
+        if path == source_path || duplicates.contains(&path) || !path.starts_with(&target_dir) {
+            continue;
+        }
+        let existing = if tokio::fs::try_exists(&path).await? {
+            read_tag_or_default(&path)
+                .ok()
+                .map(|tag| Metadata::from_tag(&tag))
+        } else {
+            None
+        };
+        match existing {
+            Some(existing)
+                if existing.musicbrainz_recording_id.as_ref() == Some(recording_id)
+                    && existing.musicbrainz_release_id.as_ref() == Some(release_id) =>
+            {
+                duplicates.push(path)
+            }
+            // Changed or removed outside of tagbrain.
+            Some(existing) => library::record(&path, &existing).await,
+            None => library::remove(&path).await,
+        }
+    }
+    Ok(duplicates)
+}
+
+/// Append number to the file name until no file exists at the path.
+async fn free_path(path: &Path) -> eyre::Result<PathBuf> {
+    if !tokio::fs::try_exists(path).await? {
+        return Ok(path.to_path_buf());
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let ext = path.extension().map(|s| s.to_string_lossy().to_string());
+    for i in 1.. {
+        let file_name = match &ext {
+            Some(ext) => format!("{} ({}).{}", stem, i, ext),
+            None => format!("{} ({})", stem, i),
+        };
+        let new_path = path.with_file_name(file_name);
+        if !tokio::fs::try_exists(&new_path).await? {
+            return Ok(new_path);
+        }
+    }
+    unreachable!()
+}
+
+#[derive(Debug)]
+struct Quality {
+    lossless: bool,
+    /// Bitrate in kbps
+    bitrate: u32,
+    sample_rate: u32,
+    bit_depth: u8,
+}
+
+impl Quality {
+    fn read(path: &Path) -> eyre::Result<Self> {
+        let file = read_from_path(path).wrap_err("Failed to read file")?;
+        let properties = file.properties();
+        Ok(Self {
+            lossless: is_lossless(path, file.file_type())?,
+            bitrate: properties.audio_bitrate().unwrap_or(0),
+            sample_rate: properties.sample_rate().unwrap_or(0),
+            bit_depth: properties.bit_depth().unwrap_or(0),
+        })
+    }
+
+    /// Lossless always wins. Lossless files are compared by resolution and lossy files by bitrate.
+    fn rank(&self) -> (bool, u32, u32, u32) {
+        if self.lossless {
+            (true, self.bit_depth as u32, self.sample_rate, self.bitrate)
+        } else {
+            (false, self.bitrate, self.sample_rate, 0)
+        }
+    }
+}
+
+/// Containers which can hold both lossless and lossy audio (mp4, wav and wavpack) are checked by
+/// their codec.
+fn is_lossless(path: &Path, file_type: FileType) -> eyre::Result<bool> {
+    let lossless = match file_type {
+        FileType::Flac | FileType::Aiff | FileType::Ape => true,
+        FileType::Mp4 => is_lossless_mp4(read_file::<Mp4File>(path)?.properties().codec()),
+        FileType::Wav => is_lossless_wav(read_file::<WavFile>(path)?.properties().format()),
+        FileType::WavPack => read_file::<WavPackFile>(path)?.properties().is_lossless(),
+        _ => false,
+    };
+    Ok(lossless)
+}
+
+fn is_lossless_mp4(codec: &Mp4Codec) -> bool {
+    matches!(codec, Mp4Codec::ALAC | Mp4Codec::FLAC)
+}
+
+fn is_lossless_wav(format: &WavFormat) -> bool {
+    matches!(format, WavFormat::PCM | WavFormat::IEEE_FLOAT)
+}
+
+fn read_file<F: AudioFile>(path: &Path) -> eyre::Result<F> {
+    let mut file = std::fs::File::open(path)?;
+    F::read_from(&mut file, ParseOptions::new()).wrap_err("Failed to read file")
+}
+
+impl std::fmt::Display for Quality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.lossless {
+            write!(f, "lossless {}bit/{}Hz", self.bit_depth, self.sample_rate)
+        } else {
+            write!(f, "{}kbps/{}Hz", self.bitrate, self.sample_rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use lofty::{file::FileType, iff::wav::WavFormat, mp4::Mp4Codec};
+
+    use super::Quality;
+
+    fn lossless(bit_depth: u8, sample_rate: u32, bitrate: u32) -> Quality {
+        Quality {
+            lossless: true,
+            bitrate,
+            sample_rate,
+            bit_depth,
+        }
+    }
+
+    fn lossy(bitrate: u32, sample_rate: u32) -> Quality {
+        Quality {
+            lossless: false,
+            bitrate,
+            sample_rate,
+            bit_depth: 0,
+        }
+    }
+
+    #[test]
+    fn quality_rank() {
+        // Each quality is higher than the previous one.
+        let ordered = [
+            lossy(128, 44100),
+            lossy(192, 44100),
+            lossy(320, 44100),
+            lossy(320, 48000),
+            lossless(16, 44100, 700),
+            lossless(16, 44100, 900),
+            lossless(16, 48000, 600),
+            lossless(24, 44100, 600),
+            lossless(24, 96000, 2000),
+        ];
+        for pair in ordered.windows(2) {
+            assert!(
+                pair[0].rank() < pair[1].rank(),
+                "{} should be lower than {}",
+                pair[0],
+                pair[1]
+            );
+        }
+        // Lossless wins even if bitrate is lower.
+        assert!(lossy(1411, 96000).rank() < lossless(16, 44100, 400).rank());
+        assert_eq!(lossy(320, 44100).rank(), lossy(320, 44100).rank());
+    }
+
+    #[test]
+    fn lossless_detection() {
+        // These types are decided without reading the file.
+        let path = Path::new("/nonexistent");
+        let cases = [
+            (FileType::Flac, true),
+            (FileType::Aiff, true),
+            (FileType::Ape, true),
+            (FileType::Mpeg, false),
+            (FileType::Aac, false),
+            (FileType::Opus, false),
+            (FileType::Vorbis, false),
+        ];
+        for (file_type, expected) in cases {
+            assert_eq!(
+                super::is_lossless(path, file_type).unwrap(),
+                expected,
+                "{:?}",
+                file_type
+            );
+        }
+
+        assert!(super::is_lossless_mp4(&Mp4Codec::ALAC));
+        assert!(super::is_lossless_mp4(&Mp4Codec::FLAC));
+        assert!(!super::is_lossless_mp4(&Mp4Codec::AAC));
+        assert!(!super::is_lossless_mp4(&Mp4Codec::MP3));
+        assert!(!super::is_lossless_mp4(&Mp4Codec::Unknown("mp4a".into())));
+
+        assert!(super::is_lossless_wav(&WavFormat::PCM));
+        assert!(super::is_lossless_wav(&WavFormat::IEEE_FLOAT));
+        // MPEG layer 3 in wav
+        assert!(!super::is_lossless_wav(&WavFormat::Other(0x55)));
+    }
+
+    #[tokio::test]
+    async fn free_path() {
+        let dir = std::env::temp_dir().join(format!("tagbrain-free_path-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("01 title.flac");
+        assert_eq!(super::free_path(&path).await.unwrap(), path);
+
+        std::fs::write(&path, b"").unwrap();
+        assert_eq!(
+            super::free_path(&path).await.unwrap(),
+            dir.join("01 title (1).flac")
+        );
+
+        std::fs::write(dir.join("01 title (1).flac"), b"").unwrap();
+        std::fs::write(dir.join("01 title (2).flac"), b"").unwrap();
+        assert_eq!(
+            super::free_path(&path).await.unwrap(),
+            dir.join("01 title (3).flac")
+        );
+
+        let no_ext = dir.join("title");
+        std::fs::write(&no_ext, b"").unwrap();
+        assert_eq!(
+            super::free_path(&no_ext).await.unwrap(),
+            dir.join("title (1)")
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,12 @@
-use eyre::{eyre, Context};
-use lofty::{
-    config::WriteOptions,
-    tag::{Tag, TagExt},
-};
+use eyre::eyre;
+use lofty::tag::Tag;
 use std::path::Path;
 
-use crate::config::CONFIG;
+use crate::{
+    config::{ConflictPolicy, CONFIG},
+    job::utils::{self, copy_file_with_tag},
+    watcher::mark_written,
+};
 
 #[tracing::instrument(skip(new_tag))]
 pub(super) async fn save_file(
@@ -13,17 +14,51 @@ pub(super) async fn save_file(
     target_path: &Path,
     new_tag: Tag,
 ) -> eyre::Result<()> {
-    if let Ok(exist) = tokio::fs::try_exists(&target_path).await {
-        if exist && !CONFIG.read().overwrite {
-            return Err(eyre!("File already exists! Skipping..."));
+    tokio::fs::create_dir_all(target_path.parent().unwrap()).await?;
+    copy_file_with_tag(source_path, target_path, &new_tag).await
+}
+
+/// Move the source file to `target_path` and write tag to it.
+///
+/// The file is copied to a hidden temp path next to the target and renamed into place after the tag
+/// is written, so an interrupted job never leaves a half-written file at the target path.
+/// The source is deleted only after that.
+#[tracing::instrument(skip(new_tag))]
+pub(super) async fn move_file_with_tag(
+    source_path: &Path,
+    target_path: &Path,
+    new_tag: Tag,
+) -> eyre::Result<()> {
+    tokio::fs::create_dir_all(target_path.parent().unwrap()).await?;
+    utils::move_file_with_tag(source_path, target_path, &new_tag).await
+}
+
+/// Write tag to the source file and rename it to `target_path` in the same directory.
+/// The tag is written to a temp copy which replaces the source, so the original file is never
+/// missing or half-written even if the job is interrupted.
+#[tracing::instrument(skip(new_tag))]
+pub(super) async fn save_in_place(
+    source_path: &Path,
+    target_path: &Path,
+    new_tag: Tag,
+) -> eyre::Result<()> {
+    let rename = source_path != target_path;
+    if rename {
+        if let Ok(exist) = tokio::fs::try_exists(&target_path).await {
+            if exist && CONFIG.read().conflict_policy() != ConflictPolicy::Overwrite {
+                return Err(eyre!("File already exists! Skipping..."));
+            }
         }
     }
-    tokio::fs::create_dir_all(target_path.parent().unwrap()).await?;
-    tokio::fs::copy(source_path, &target_path).await?;
 
-    new_tag
-        .save_to_path(target_path, WriteOptions::new())
-        .wrap_err("Failed to write tag")?;
+    // Prevent watcher from scanning the file again.
+    mark_written(source_path);
+    mark_written(target_path);
+    mark_written(&utils::temp_path_for(target_path));
+
+    utils::move_file_with_tag(source_path, target_path, &new_tag).await?;
+
+    mark_written(target_path);
 
     Ok(())
 }
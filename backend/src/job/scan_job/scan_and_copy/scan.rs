@@ -1,37 +1,142 @@
-use std::path::Path;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use eyre::{eyre, Context};
 use lofty::tag::Tag;
+use once_cell::sync::Lazy;
 use serde::Deserialize;
-use tracing::{info, warn};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, warn};
 
 use crate::{
-    api::{
-        acoustid::AcoustidClient,
-        musicbrainz::{recording::RecordingRes, MusicbrainzClient},
+    api::musicbrainz::{recording::RecordingRes, MusicbrainzClient},
+    config::{ScannerKind, CONFIG},
+    interface::{
+        candidate::{Candidate, ScoreBreakdown},
+        metadata::{write_metadata, Metadata},
     },
-    config::CONFIG,
-    interface::metadata::{write_metadata, Metadata},
     job::{
-        scan_job::scan_and_copy::utils::find_best_release_and_recording,
-        utils::{fetch_cover_art, read_tag_or_default, response_to_metadata},
+        acoustid_submit_job::queue_submission,
+        scan_job::scan_and_copy::utils::{
+            find_best_release_and_recording, rank_releases_and_recordings, PoolEntry,
+        },
+        utils::{read_tag_or_default, refresh_tag, response_to_metadata, CoverArt},
     },
 };
 
-use super::ScannerInfo;
+use super::{NoMatchError, ScannerInfo};
 
 mod acoustid_scanner;
+mod album_scanner;
 mod musicbrainz_search_scanner;
+mod path_scanner;
 
 pub(super) struct ScanRes {
     pub old_metadata: Metadata,
     pub new_metadata: Metadata,
     pub new_tag: Tag,
     pub scanner_info: ScannerInfo,
+    /// Top candidates considered in scan.
+    pub candidates: Vec<Candidate>,
+    /// Score breakdown of the selected match.
+    pub score: Option<ScoreBreakdown>,
+}
+pub(super) struct AlbumScanRes {
+    /// Files matched to tracks of the selected release.
+    pub matched: Vec<(PathBuf, ScanRes)>,
+    /// Files which could not be matched to any track of the selected release.
+    pub unmatched: Vec<PathBuf>,
 }
 struct ScannerRes {
     log: ScannerInfo,
     recordings: Vec<RecordingRes>,
+    /// Tag used to rank recordings instead of the tag of the file.
+    search_tag: Option<Tag>,
+    /// Acoustid score of each recording id.
+    acoustid_scores: HashMap<String, f64>,
+}
+
+struct ScannerInput<'a> {
+    path: &'a Path,
+    tag: &'a Tag,
+    fp: &'a FpcalcResult,
+}
+
+/// Finds candidate recordings of a file.
+trait Scanner {
+    async fn scan(&self, input: &ScannerInput<'_>) -> eyre::Result<ScannerRes>;
+}
+
+async fn run_scanner(kind: ScannerKind, input: &ScannerInput<'_>) -> eyre::Result<ScannerRes> {
+    match kind {
+        ScannerKind::AcoustId => acoustid_scanner::AcoustidScanner.scan(input).await,
+        ScannerKind::MusicbrainzSearch => {
+            musicbrainz_search_scanner::MusicbrainzSearchScanner
+                .scan(input)
+                .await
+        }
+        ScannerKind::Path => path_scanner::PathScanner.scan(input).await,
+    }
+}
+
+/// Run scanners in the configured chain and rank all matches found by them.
+/// Returned pool is sorted by score in descending order.
+async fn run_scanner_chain(input: &ScannerInput<'_>) -> Vec<PoolEntry> {
+    let (scanner_config, default_threshold, acoustid_weight) = {
+        let config = CONFIG.read();
+        (
+            config.scanner.clone(),
+            config.release_selector.threshold,
+            config.release_selector.acoustid_score.weight,
+        )
+    };
+
+    let mut pool = vec![];
+    for entry in scanner_config.chain.iter().filter(|entry| entry.enabled) {
+        let res = match run_scanner(entry.kind, input).await {
+            Ok(res) => res,
+            Err(e) => {
+                info!("Scanner {:?} failed: {}", entry.kind, e);
+                continue;
+            }
+        };
+        let mut ranked = rank_releases_and_recordings(
+            res.recordings,
+            res.search_tag.as_ref().unwrap_or(input.tag),
+            Some(input.fp.duration),
+        );
+        for m in &mut ranked {
+            if let Some(score) = res.acoustid_scores.get(&m.recording.id) {
+                m.score.acoustid_score = Some(*score);
+                m.score.add("acoustid_score", *score, acoustid_weight);
+            }
+        }
+        let threshold = entry.threshold.unwrap_or(default_threshold);
+        let found = ranked
+            .iter()
+            .any(|m| m.score.threshold_total() >= threshold);
+        pool.extend(ranked.into_iter().map(|scored| PoolEntry {
+            scanner: res.log.clone(),
+            threshold,
+            scored,
+        }));
+        if !found {
+            info!("Scanner {:?} found no match above threshold.", entry.kind);
+        } else if !scanner_config.merge {
+            break;
+        }
+    }
+
+    pool.sort_by(|a, b| {
+        b.scored
+            .score
+            .total
+            .partial_cmp(&a.scored.score.total)
+            .expect("This should not happen.")
+    });
+    pool
 }
 
 #[derive(Deserialize, Debug)]
@@ -39,7 +144,14 @@ pub(super) struct FpcalcResult {
     pub duration: f64,
     pub fingerprint: String,
 }
+
+/// Limits number of fpcalc processes in total, since album jobs fingerprint several files at once.
+/// Like the number of jobs, the limit is read from `concurrency` only once.
+static FPCALC_PERMITS: Lazy<Semaphore> =
+    Lazy::new(|| Semaphore::new(CONFIG.read().concurrency.max(1)));
+
 async fn calc_fingerprint(path: &Path) -> eyre::Result<FpcalcResult> {
+    let _permit = FPCALC_PERMITS.acquire().await?;
     let output = tokio::process::Command::new("fpcalc")
         .arg(path)
         .arg("-json")
@@ -52,44 +164,75 @@ async fn calc_fingerprint(path: &Path) -> eyre::Result<FpcalcResult> {
     Ok(json)
 }
 
-pub(super) async fn scan(path: &Path) -> eyre::Result<ScanRes> {
+/// If `dry_run` is true, fingerprint submission and cover art fetching are skipped.
+pub(super) async fn scan(path: &Path, dry_run: bool) -> eyre::Result<ScanRes> {
     let mut tag = read_tag_or_default(path)?;
 
     let old_metadata = Metadata::from_tag(&tag);
 
-    let fp = calc_fingerprint(path)
-        .await
-        .wrap_err("Failed to calc fingerprint")?;
-
     if old_metadata.musicbrainz_release_id.is_some()
         && old_metadata.musicbrainz_recording_id.is_some()
-        && CONFIG.read().force
     {
-        return Ok(ScanRes {
-            old_metadata: old_metadata.clone(),
-            new_metadata: old_metadata,
-            new_tag: tag,
-            scanner_info: ScannerInfo::Skip,
-        });
+        let (refresh_existing, force) = {
+            let config = CONFIG.read();
+            (config.refresh_existing, config.force)
+        };
+        if refresh_existing {
+            return refresh(tag, old_metadata, dry_run).await;
+        }
+        if force {
+            return Ok(ScanRes {
+                old_metadata: old_metadata.clone(),
+                new_metadata: old_metadata,
+                new_tag: tag,
+                scanner_info: ScannerInfo::Skip,
+                candidates: vec![],
+                score: None,
+            });
+        }
     }
 
-    let (scanner_res, submit_fingerprint) =
-        if let Ok(res) = acoustid_scanner::acoustid_scanner(path, &fp).await {
-            (res, false)
-        } else {
-            info!("Acoustid scanner failed. Falling back to musicbrainz search scanner.");
-            (
-                musicbrainz_search_scanner::musicbrainz_search_scanner(&tag).await?,
-                true,
-            )
-        };
+    let fp = calc_fingerprint(path)
+        .await
+        .wrap_err("Failed to calc fingerprint")?;
 
-    let (best_recording, best_release, best_score) = {
-        find_best_release_and_recording(scanner_res.recordings, &tag)
-            .ok_or_else(|| eyre!("Failed to find best match"))?
-    };
+    let pool = run_scanner_chain(&ScannerInput {
+        path,
+        tag: &tag,
+        fp: &fp,
+    })
+    .await;
+    if pool.is_empty() {
+        return Err(eyre!("No scanner found any recording"));
+    }
 
+    let mut candidates = Vec::<Candidate>::new();
+    for entry in &pool {
+        if candidates.len() >= CONFIG.read().candidate_count {
+            break;
+        }
+        let candidate = entry.scored.to_candidate();
+        if !candidates.iter().any(|c| {
+            c.recording_id == candidate.recording_id && c.release_id == candidate.release_id
+        }) {
+            candidates.push(candidate);
+        }
+    }
+    let Some(best) = find_best_release_and_recording(&pool) else {
+        return Err(NoMatchError { candidates }.into());
+    };
+    let scanner_info = best.scanner.clone();
+    let submit_fingerprint = !matches!(scanner_info, ScannerInfo::AcoustId { .. });
     let mb_client = MusicbrainzClient::new();
+    let best_recording = if let ScannerInfo::AcoustId { .. } = scanner_info {
+        // Recording from acoustid lacks some fields like sort name.
+        mb_client.recording(&best.scored.recording.id).await?
+    } else {
+        best.scored.recording.clone()
+    };
+    let best_release = &best.scored.release;
+    let best_score = best.scored.score.clone();
+
     let release = mb_client.release(&best_release.id).await?;
 
     info!(
@@ -98,30 +241,35 @@ pub(super) async fn scan(path: &Path) -> eyre::Result<ScanRes> {
         best_release.id,
         best_recording.title,
         best_recording.id,
-        best_score
+        best_score.total
     );
-
-    if submit_fingerprint {
-        let acoustid_client = AcoustidClient::new();
-        let _ = acoustid_client
-            .submit(
-                &best_recording.id,
-                &fp.fingerprint,
-                fp.duration.round() as u32,
-            )
-            .await;
-        info!("Submitted fingerprint to acoustid: {}", best_recording.id);
+    for component in &best_score.components {
+        debug!(
+            "  {}: {} * {} = {}",
+            component.name, component.value, component.weight, component.contribution
+        );
     }
 
-    if tag.picture_count() == 0 {
-        let cover_art = fetch_cover_art(&release.id).await;
-
-        match cover_art {
-            Ok(cover_art) => tag.push_picture(cover_art),
-            Err(e) => warn!("Failed to fetch cover art: {}", e),
+    if submit_fingerprint && !dry_run && CONFIG.read().acoustid_submit.enabled {
+        match queue_submission(
+            &best_recording.id,
+            &fp.fingerprint,
+            fp.duration.round() as u32,
+            path,
+        )
+        .await
+        {
+            Ok(()) => info!("Queued fingerprint for acoustid: {}", best_recording.id),
+            Err(e) => warn!("Failed to queue fingerprint submission: {:?}", e),
         }
     }
 
+    if !dry_run {
+        CoverArt::new(&release.id, Some(&release.release_group.id))
+            .embed(&mut tag)
+            .await;
+    }
+
     let new_metadata = response_to_metadata(best_recording, release)?;
     write_metadata(&mut tag, new_metadata.clone());
 
@@ -129,6 +277,36 @@ pub(super) async fn scan(path: &Path) -> eyre::Result<ScanRes> {
         old_metadata,
         new_metadata,
         new_tag: tag,
-        scanner_info: scanner_res.log,
+        scanner_info,
+        candidates,
+        score: Some(best_score),
     })
 }
+
+/// Tag the file with the current data of musicbrainz ids in the file.
+async fn refresh(mut tag: Tag, old_metadata: Metadata, dry_run: bool) -> eyre::Result<ScanRes> {
+    info!(
+        "Refreshing with existing ids: release {:?}, recording {:?}",
+        old_metadata.musicbrainz_release_id, old_metadata.musicbrainz_recording_id
+    );
+    let new_metadata = refresh_tag(&mut tag, &old_metadata).await?;
+    if !dry_run {
+        if let Some(cover_art) = CoverArt::from_metadata(&new_metadata) {
+            cover_art.embed(&mut tag).await;
+        }
+    }
+
+    Ok(ScanRes {
+        old_metadata,
+        new_metadata,
+        new_tag: tag,
+        scanner_info: ScannerInfo::Refresh,
+        candidates: vec![],
+        score: None,
+    })
+}
+
+/// Scan all files in a directory as one release.
+pub(super) async fn scan_album(paths: &[PathBuf], dry_run: bool) -> eyre::Result<AlbumScanRes> {
+    album_scanner::album_scanner(paths, dry_run).await
+}
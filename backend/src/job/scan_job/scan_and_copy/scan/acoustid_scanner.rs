@@ -1,16 +1,34 @@
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
 use eyre::{eyre, Result};
 use tracing::{debug, info, warn};
 
 use crate::{
-    api::{acoustid::AcoustidClient, musicbrainz::MusicbrainzClient},
-    config::CONFIG,
+    api::{
+        acoustid::{AcoustidClient, LookupResEntryRecording},
+        musicbrainz::{
+            recording::{
+                RecordingRes, RecordingResRelease, RecordingResReleaseGroup,
+                RecordingResReleaseMedia,
+            },
+            ArtistCredit, ArtistCreditArtist, MusicbrainzClient,
+        },
+    },
+    config::{DatePreference, CONFIG},
     job::scan_job::scan_and_copy::{scan::ScannerRes, ScannerInfo},
 };
 
-use super::FpcalcResult;
+use super::{FpcalcResult, Scanner, ScannerInput};
 
+pub(super) struct AcoustidScanner;
+
+impl Scanner for AcoustidScanner {
+    async fn scan(&self, input: &ScannerInput<'_>) -> Result<ScannerRes> {
+        acoustid_scanner(input.path, input.fp).await
+    }
+}
+
+/// Lookup acoustid and collect recordings of every result above `acoustid_match_threshold`.
 #[tracing::instrument(skip(fp))]
 pub(super) async fn acoustid_scanner(
     path: &Path,
@@ -22,45 +40,149 @@ pub(super) async fn acoustid_scanner(
     let acoustid_res = acoustid_client
         .lookup(&fp.fingerprint, fp.duration.round() as u32)
         .await?;
-    let Some(best) = acoustid_res
+
+    let threshold = CONFIG.read().acoustid_match_threshold;
+    let Some(best_score) = acoustid_res
         .results
-        .into_iter()
-        .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap())
+        .iter()
+        .map(|result| result.score)
+        .max_by(|a, b| a.partial_cmp(b).unwrap())
     else {
         return Err(eyre!("No acoustid match found."));
     };
-
-    let Some(best_recordings) = best.recordings else {
-        return Err(eyre!("No acoustid match found."));
-    };
-
-    if best.score < CONFIG.read().acoustid_match_threshold {
+    if best_score < threshold {
         return Err(eyre!(
             "Best acoustid match score is too low. Score: {}",
-            best.score
+            best_score
         ));
     }
 
-    debug!(
-        "Best match acoustid was {} (score: {})",
-        best.id, best.score
-    );
-
-    let recordings = futures::future::join_all(best_recordings.iter().map(|id| async move {
-        let mb_client = MusicbrainzClient::new();
-        let res = mb_client.recording(&id.id).await;
-        if let Err(e) = &res {
-            warn!("Failed to get recording {}: {:?}", id.id, e);
+    // Same recording may be linked to multiple results. Highest score is used.
+    let mut scores = HashMap::<String, f64>::new();
+    let mut lookup_recordings = Vec::<LookupResEntryRecording>::new();
+    for result in acoustid_res.results {
+        if result.score < threshold {
+            continue;
+        }
+        debug!("Acoustid match {} (score: {})", result.id, result.score);
+        for recording in result.recordings.into_iter().flatten() {
+            match scores.get_mut(&recording.id) {
+                Some(score) => *score = score.max(result.score),
+                None => {
+                    scores.insert(recording.id.clone(), result.score);
+                    lookup_recordings.push(recording);
+                }
+            }
         }
-        res
-    }))
-    .await
-    .into_iter()
-    .filter_map(|res| res.ok())
-    .collect::<Vec<_>>();
+    }
+    if lookup_recordings.is_empty() {
+        return Err(eyre!("No acoustid match found."));
+    }
+
+    // Acoustid has no release status and first release date of release groups.
+    // If they are used to select releases, all recordings are fetched from musicbrainz.
+    let fetch_all = {
+        let selector = &CONFIG.read().release_selector;
+        !selector.status.preferred.is_empty() || selector.date.prefer == DatePreference::Original
+    };
+
+    // Recordings without metadata in acoustid are fetched from musicbrainz.
+    let recordings =
+        futures::future::join_all(lookup_recordings.into_iter().map(|recording| async move {
+            if !fetch_all {
+                if let Some(recording) = to_recording_res(&recording) {
+                    return Some(recording);
+                }
+            }
+            let mb_client = MusicbrainzClient::new();
+            match mb_client.recording(&recording.id).await {
+                Ok(recording) => Some(recording),
+                Err(e) => {
+                    warn!("Failed to get recording {}: {:?}", recording.id, e);
+                    None
+                }
+            }
+        }))
+        .await
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
 
     Ok(ScannerRes {
-        log: ScannerInfo::AcoustId { score: best.score },
+        log: ScannerInfo::AcoustId { score: best_score },
         recordings,
+        search_tag: None,
+        acoustid_scores: scores,
+    })
+}
+
+/// Convert recording in acoustid response to musicbrainz one.
+/// Returns None if acoustid doesn't have enough metadata of the recording.
+fn to_recording_res(recording: &LookupResEntryRecording) -> Option<RecordingRes> {
+    let title = recording.title.clone()?;
+    let releasegroups = recording.releasegroups.as_ref()?;
+
+    let releases = releasegroups
+        .iter()
+        .flat_map(|group| {
+            let release_group = RecordingResReleaseGroup {
+                id: group.id.clone(),
+                title: group.title.clone().unwrap_or_default(),
+                primary_type: group.r#type.clone(),
+                secondary_types: group.secondarytypes.clone(),
+                first_release_date: None,
+            };
+            group
+                .releases
+                .iter()
+                .flatten()
+                .map(move |release| RecordingResRelease {
+                    id: release.id.clone(),
+                    title: release
+                        .title
+                        .clone()
+                        .unwrap_or_else(|| release_group.title.clone()),
+                    country: release.country.clone(),
+                    status: None,
+                    date: release.date.as_ref().and_then(|d| d.to_date_string()),
+                    media: release.mediums.as_ref().map(|mediums| {
+                        mediums
+                            .iter()
+                            .map(|medium| RecordingResReleaseMedia {
+                                format: medium.format.clone(),
+                            })
+                            .collect()
+                    }),
+                    release_group: release_group.clone(),
+                })
+        })
+        .collect::<Vec<_>>();
+    if releases.is_empty() {
+        return None;
+    }
+    let first_release_date = releases.iter().filter_map(|r| r.date.clone()).min();
+
+    let artist_credit = recording.artists.as_ref().map(|artists| {
+        artists
+            .iter()
+            .map(|artist| ArtistCredit {
+                artist: ArtistCreditArtist {
+                    id: artist.id.clone(),
+                    // acoustid doesn't have sort name.
+                    sort_name: artist.name.clone(),
+                    name: artist.name.clone(),
+                },
+                joinphrase: artist.joinphrase.clone(),
+            })
+            .collect()
+    });
+
+    Some(RecordingRes {
+        title,
+        id: recording.id.clone(),
+        length: recording.duration.map(|d| (d * 1000.0).round() as u32),
+        releases: Some(releases),
+        artist_credit,
+        first_release_date,
     })
 }
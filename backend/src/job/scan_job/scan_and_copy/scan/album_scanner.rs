@@ -0,0 +1,553 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use eyre::eyre;
+use futures::{stream, StreamExt};
+use lofty::tag::Tag;
+use tracing::{debug, info, warn};
+
+use crate::{
+    api::musicbrainz::{
+        recording::RecordingRes,
+        release::{ReleaseRes, ReleaseResMedia, ReleaseResMediaTrack},
+        MusicbrainzClient,
+    },
+    config::{AlbumSelector, CONFIG},
+    interface::metadata::{write_metadata, Metadata},
+    job::utils::{read_tag_or_default, response_to_metadata, CoverArt},
+};
+
+use super::{
+    acoustid_scanner::acoustid_scanner, calc_fingerprint, refresh, AlbumScanRes, ScanRes,
+    ScannerInfo,
+};
+
+struct AlbumFile {
+    path: PathBuf,
+    tag: Tag,
+    old_metadata: Metadata,
+    /// Duration in seconds
+    duration: f64,
+    /// Recordings found by acoustid
+    recordings: Vec<RecordingRes>,
+    acoustid_score: Option<f64>,
+}
+
+struct Assignment {
+    file_idx: usize,
+    media_idx: usize,
+    track_idx: usize,
+}
+
+/// Fingerprint the file and look it up in acoustid.
+async fn read_album_file(
+    path: PathBuf,
+    tag: Tag,
+    old_metadata: Metadata,
+) -> eyre::Result<AlbumFile> {
+    let fp = calc_fingerprint(&path).await?;
+    let (recordings, acoustid_score) = match acoustid_scanner(&path, &fp).await {
+        Ok(res) => {
+            let score = match res.log {
+                ScannerInfo::AcoustId { score } => Some(score),
+                _ => None,
+            };
+            (res.recordings, score)
+        }
+        Err(e) => {
+            info!("No acoustid match for {}: {}", path.display(), e);
+            (vec![], None)
+        }
+    };
+    Ok(AlbumFile {
+        path,
+        tag,
+        old_metadata,
+        duration: fp.duration,
+        recordings,
+        acoustid_score,
+    })
+}
+
+/// Identify all files as tracks of one release.
+#[tracing::instrument(skip_all)]
+pub(super) async fn album_scanner(paths: &[PathBuf], dry_run: bool) -> eyre::Result<AlbumScanRes> {
+    let (selector, force, refresh_existing, concurrency) = {
+        let config = CONFIG.read();
+        (
+            config.album_selector.clone(),
+            config.force,
+            config.refresh_existing,
+            config.concurrency.max(1),
+        )
+    };
+
+    let mut matched = vec![];
+    let mut unmatched = vec![];
+    // Files which already have ids are not fingerprinted.
+    let mut unidentified = vec![];
+    for path in paths {
+        let tag = match read_tag_or_default(path) {
+            Ok(tag) => tag,
+            Err(e) => {
+                warn!("Failed to read {}: {:?}", path.display(), e);
+                unmatched.push(path.clone());
+                continue;
+            }
+        };
+        let old_metadata = Metadata::from_tag(&tag);
+        let has_ids = old_metadata.musicbrainz_release_id.is_some()
+            && old_metadata.musicbrainz_recording_id.is_some();
+        if has_ids && refresh_existing {
+            match refresh(tag, old_metadata, dry_run).await {
+                Ok(res) => matched.push((path.clone(), res)),
+                Err(e) => {
+                    warn!("Failed to refresh {}: {:?}", path.display(), e);
+                    unmatched.push(path.clone());
+                }
+            }
+        } else if has_ids && force {
+            matched.push((
+                path.clone(),
+                ScanRes {
+                    old_metadata: old_metadata.clone(),
+                    new_metadata: old_metadata,
+                    new_tag: tag,
+                    scanner_info: ScannerInfo::Skip,
+                    candidates: vec![],
+                    score: None,
+                },
+            ));
+        } else {
+            unidentified.push((path.clone(), tag, old_metadata));
+        }
+    }
+
+    // Number of fpcalc processes is limited in `calc_fingerprint`.
+    let read_results = stream::iter(unidentified)
+        .map(|(path, tag, old_metadata)| async move {
+            (path.clone(), read_album_file(path, tag, old_metadata).await)
+        })
+        .buffered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut files = vec![];
+    for (path, res) in read_results {
+        match res {
+            Ok(file) => files.push(file),
+            Err(e) => {
+                warn!("Failed to fingerprint {}: {:?}", path.display(), e);
+                unmatched.push(path);
+            }
+        }
+    }
+    if files.is_empty() {
+        return Ok(AlbumScanRes { matched, unmatched });
+    }
+
+    let mut release_coverage = HashMap::<&str, usize>::new();
+    for file in &files {
+        let release_ids = file
+            .recordings
+            .iter()
+            .flat_map(|recording| recording.releases.iter().flatten())
+            .map(|release| release.id.as_str())
+            .collect::<HashSet<_>>();
+        for id in release_ids {
+            *release_coverage.entry(id).or_default() += 1;
+        }
+    }
+    let mut candidates = release_coverage.into_iter().collect::<Vec<_>>();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    candidates.truncate(selector.candidate_count);
+
+    let mb_client = MusicbrainzClient::new();
+    let mut best: Option<(ReleaseRes, Vec<Assignment>, f64)> = None;
+    for (release_id, _) in candidates {
+        let release = match mb_client.release(release_id).await {
+            Ok(release) => release,
+            Err(e) => {
+                warn!("Failed to get release {}: {:?}", release_id, e);
+                continue;
+            }
+        };
+        let assignments = assign_tracks(&files, &release, &selector);
+        let score = calc_album_score(&files, &release, &assignments, &selector);
+        debug!(
+            "Release candidate '{}({})' matched {} files with score {}",
+            release.title,
+            release.id,
+            assignments.len(),
+            score
+        );
+        if best
+            .as_ref()
+            .is_none_or(|(_, _, best_score)| score > *best_score)
+        {
+            best = Some((release, assignments, score));
+        }
+    }
+
+    let Some((release, assignments, score)) = best else {
+        return Err(eyre!("No release candidate found"));
+    };
+    let coverage = assignments.len() as f64 / files.len() as f64;
+    if coverage < selector.min_coverage {
+        return Err(eyre!(
+            "Best release '{}' covers too few files. Coverage: {}",
+            release.id,
+            coverage
+        ));
+    }
+    info!(
+        "Best match release was '{}({})' with score {} ({} of {} files)",
+        release.title,
+        release.id,
+        score,
+        assignments.len(),
+        files.len()
+    );
+
+    let cover_art = CoverArt::new(&release.id, Some(&release.release_group.id));
+
+    let mut files = files.into_iter().map(Some).collect::<Vec<_>>();
+    for assignment in assignments {
+        let file = files[assignment.file_idx].take().unwrap();
+        let track = &release.media[assignment.media_idx].tracks[assignment.track_idx];
+        let recording = RecordingRes {
+            title: track.recording.title.clone(),
+            id: track.recording.id.clone(),
+            length: track.recording.length,
+            releases: None,
+            artist_credit: track.recording.artist_credit.clone(),
+            first_release_date: None,
+        };
+        let new_metadata = match response_to_metadata(recording, release.clone()) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("Failed to create metadata: {:?}", e);
+                unmatched.push(file.path);
+                continue;
+            }
+        };
+
+        let mut tag = file.tag;
+        if !dry_run {
+            cover_art.embed(&mut tag).await;
+        }
+        write_metadata(&mut tag, new_metadata.clone());
+
+        matched.push((
+            file.path,
+            ScanRes {
+                old_metadata: file.old_metadata,
+                new_metadata,
+                new_tag: tag,
+                scanner_info: ScannerInfo::Album {
+                    score: file.acoustid_score,
+                },
+                candidates: vec![],
+                score: None,
+            },
+        ));
+    }
+    unmatched.extend(files.into_iter().flatten().map(|file| file.path));
+
+    Ok(AlbumScanRes { matched, unmatched })
+}
+
+/// Difference between file duration and track length in seconds.
+fn duration_diff(file: &AlbumFile, track: &ReleaseResMediaTrack) -> Option<f64> {
+    track
+        .length
+        .or(track.recording.length)
+        .map(|length| (length as f64 / 1000.0 - file.duration).abs())
+}
+
+fn is_same_position(
+    file: &AlbumFile,
+    media: &ReleaseResMedia,
+    track: &ReleaseResMediaTrack,
+) -> bool {
+    file.old_metadata.track == Some(track.position)
+        && file.old_metadata.disc.unwrap_or(1) == media.position
+}
+
+/// Assign files to tracks of the release.
+/// Files identified by acoustid are assigned first, and remaining files are assigned by track position in tag.
+fn assign_tracks(
+    files: &[AlbumFile],
+    release: &ReleaseRes,
+    selector: &AlbumSelector,
+) -> Vec<Assignment> {
+    let mut taken = HashSet::<(usize, usize)>::new();
+    let mut assignments = vec![];
+
+    for (file_idx, file) in files.iter().enumerate() {
+        let recording_ids = file
+            .recordings
+            .iter()
+            .map(|recording| recording.id.as_str())
+            .collect::<HashSet<_>>();
+        let best = release
+            .media
+            .iter()
+            .enumerate()
+            .flat_map(|(media_idx, media)| {
+                media
+                    .tracks
+                    .iter()
+                    .enumerate()
+                    .map(move |(track_idx, track)| (media_idx, media, track_idx, track))
+            })
+            .filter(|(media_idx, _, track_idx, track)| {
+                !taken.contains(&(*media_idx, *track_idx))
+                    && recording_ids.contains(track.recording.id.as_str())
+            })
+            .min_by_key(|(_, media, _, track)| {
+                (
+                    !is_same_position(file, media, track),
+                    duration_diff(file, track).map_or(u64::MAX, |diff| (diff * 1000.0) as u64),
+                )
+            });
+        if let Some((media_idx, _, track_idx, _)) = best {
+            taken.insert((media_idx, track_idx));
+            assignments.push(Assignment {
+                file_idx,
+                media_idx,
+                track_idx,
+            });
+        }
+    }
+
+    for (file_idx, file) in files.iter().enumerate() {
+        if assignments.iter().any(|a| a.file_idx == file_idx) {
+            continue;
+        }
+        let position = release
+            .media
+            .iter()
+            .enumerate()
+            .find_map(|(media_idx, media)| {
+                media
+                    .tracks
+                    .iter()
+                    .position(|track| is_same_position(file, media, track))
+                    .map(|track_idx| (media_idx, track_idx))
+            });
+        let Some((media_idx, track_idx)) = position else {
+            continue;
+        };
+        if taken.contains(&(media_idx, track_idx)) {
+            continue;
+        }
+        let track = &release.media[media_idx].tracks[track_idx];
+        if duration_diff(file, track).is_some_and(|diff| diff > selector.duration_tolerance) {
+            continue;
+        }
+        taken.insert((media_idx, track_idx));
+        assignments.push(Assignment {
+            file_idx,
+            media_idx,
+            track_idx,
+        });
+    }
+
+    assignments
+}
+
+/// Calculate how well the release matches the whole group of files.
+fn calc_album_score(
+    files: &[AlbumFile],
+    release: &ReleaseRes,
+    assignments: &[Assignment],
+    selector: &AlbumSelector,
+) -> f64 {
+    if assignments.is_empty() {
+        return 0.0;
+    }
+    let file_count = files.len() as f64;
+    let track_count = release
+        .media
+        .iter()
+        .map(|media| media.track_count)
+        .sum::<u32>() as f64;
+
+    let coverage = assignments.len() as f64 / file_count;
+    let track_count_score = if track_count > 0.0 {
+        file_count.min(track_count) / file_count.max(track_count)
+    } else {
+        0.0
+    };
+
+    let mut same_position = 0;
+    let mut duration_scores = vec![];
+    for assignment in assignments {
+        let file = &files[assignment.file_idx];
+        let media = &release.media[assignment.media_idx];
+        let track = &media.tracks[assignment.track_idx];
+        if is_same_position(file, media, track) {
+            same_position += 1;
+        }
+        if let Some(diff) = duration_diff(file, track) {
+            duration_scores.push(1.0 - (diff / selector.duration_tolerance).min(1.0));
+        }
+    }
+    let position_score = same_position as f64 / assignments.len() as f64;
+    let duration_score = if duration_scores.is_empty() {
+        0.0
+    } else {
+        duration_scores.iter().sum::<f64>() / duration_scores.len() as f64
+    };
+
+    // Coverage is the most important. Other scores are used to choose between releases which cover the same files.
+    2.0 * coverage + track_count_score + position_score + duration_score
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use lofty::tag::{Tag, TagType};
+
+    use crate::{
+        api::musicbrainz::{recording::RecordingRes, release::ReleaseRes},
+        config::AlbumSelector,
+        interface::metadata::Metadata,
+    };
+
+    use super::{assign_tracks, calc_album_score, AlbumFile};
+
+    /// Release with one medium. Each track is `(recording id, length in seconds)`.
+    fn release(tracks: &[(&str, u32)]) -> ReleaseRes {
+        let tracks = tracks
+            .iter()
+            .enumerate()
+            .map(|(i, (id, length))| {
+                serde_json::json!({
+                    "number": (i + 1).to_string(),
+                    "title": id,
+                    "id": format!("track-{}", id),
+                    "position": i + 1,
+                    "length": length * 1000,
+                    "recording": { "id": id, "title": id, "length": length * 1000 },
+                })
+            })
+            .collect::<Vec<_>>();
+        serde_json::from_value(serde_json::json!({
+            "id": "release",
+            "title": "release",
+            "media": [{ "position": 1, "tracks": tracks, "track-count": tracks.len() }],
+            "release-group": { "id": "release-group", "title": "release" },
+        }))
+        .unwrap()
+    }
+
+    /// File with track number in tag and recordings found by acoustid.
+    fn file(track: Option<u32>, duration: f64, recording_ids: &[&str]) -> AlbumFile {
+        AlbumFile {
+            path: PathBuf::new(),
+            tag: Tag::new(TagType::Id3v2),
+            old_metadata: Metadata {
+                track,
+                ..Default::default()
+            },
+            duration,
+            recordings: recording_ids
+                .iter()
+                .map(|id| RecordingRes {
+                    title: id.to_string(),
+                    id: id.to_string(),
+                    length: None,
+                    releases: None,
+                    artist_credit: None,
+                    first_release_date: None,
+                })
+                .collect(),
+            acoustid_score: None,
+        }
+    }
+
+    /// `(file index, track index)` of each assignment sorted by file index.
+    fn assigned(files: &[AlbumFile], release: &ReleaseRes) -> Vec<(usize, usize)> {
+        let mut assigned = assign_tracks(files, release, &AlbumSelector::default())
+            .iter()
+            .map(|a| (a.file_idx, a.track_idx))
+            .collect::<Vec<_>>();
+        assigned.sort();
+        assigned
+    }
+
+    #[test]
+    fn assign_by_acoustid_and_position() {
+        let release = release(&[("a", 200), ("b", 180), ("c", 240)]);
+        let files = [
+            file(None, 180.0, &["b"]),
+            file(Some(3), 240.0, &[]),
+            file(None, 200.0, &["x", "a"]),
+        ];
+        assert_eq!(assigned(&files, &release), vec![(0, 1), (1, 2), (2, 0)]);
+    }
+
+    #[test]
+    fn assign_more_files_than_tracks() {
+        let release = release(&[("a", 200), ("b", 180)]);
+        let files = [
+            file(Some(1), 200.0, &["a"]),
+            file(Some(2), 180.0, &["b"]),
+            file(Some(3), 240.0, &[]),
+        ];
+        assert_eq!(assigned(&files, &release), vec![(0, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn assign_duplicate_tracks() {
+        let release = release(&[("a", 200), ("b", 180)]);
+        // Same track number in tag.
+        let files = [file(Some(1), 200.0, &[]), file(Some(1), 200.0, &[])];
+        assert_eq!(assigned(&files, &release), vec![(0, 0)]);
+        // Same recording found by acoustid. Files are assigned in order and a track is never reused.
+        let files = [file(Some(2), 200.0, &["a"]), file(Some(1), 200.0, &["a"])];
+        assert_eq!(assigned(&files, &release), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn assign_unmatched_files() {
+        let release = release(&[("a", 200), ("b", 180)]);
+        let files = [
+            // Recording not in the release and no track number.
+            file(None, 200.0, &["x"]),
+            // Track number matches but duration is too different.
+            file(Some(2), 300.0, &[]),
+        ];
+        assert_eq!(assigned(&files, &release), vec![]);
+    }
+
+    #[test]
+    fn album_score() {
+        let selector = AlbumSelector::default();
+        let files = [file(Some(1), 200.0, &["a"]), file(Some(2), 180.0, &["b"])];
+
+        let full = release(&[("a", 200), ("b", 180)]);
+        let assignments = assign_tracks(&files, &full, &selector);
+        let full_score = calc_album_score(&files, &full, &assignments, &selector);
+        assert_eq!(full_score, 5.0);
+
+        // Release with more tracks than files.
+        let larger = release(&[("a", 200), ("b", 180), ("c", 240), ("d", 240)]);
+        let assignments = assign_tracks(&files, &larger, &selector);
+        let larger_score = calc_album_score(&files, &larger, &assignments, &selector);
+        assert!(larger_score < full_score);
+
+        // Release which covers only one file.
+        let partial = release(&[("a", 200), ("c", 300)]);
+        let assignments = assign_tracks(&files, &partial, &selector);
+        let partial_score = calc_album_score(&files, &partial, &assignments, &selector);
+        assert!(partial_score < larger_score);
+
+        assert_eq!(calc_album_score(&files, &full, &[], &selector), 0.0);
+    }
+}
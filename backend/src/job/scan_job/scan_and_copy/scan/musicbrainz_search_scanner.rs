@@ -1,22 +1,178 @@
+use std::collections::HashMap;
+
 use eyre::eyre;
-use lofty::tag::{Accessor, Tag};
+use lofty::tag::{Accessor, ItemKey, Tag};
+use tracing::{debug, info};
+
+use crate::{
+    api::musicbrainz::MusicbrainzClient,
+    config::CONFIG,
+    job::scan_job::scan_and_copy::{utils::rank_releases_and_recordings, ScannerInfo},
+};
+
+use super::{Scanner, ScannerInput, ScannerRes};
 
-use crate::{api::musicbrainz::MusicbrainzClient, job::scan_job::scan_and_copy::ScannerInfo};
+pub(super) struct MusicbrainzSearchScanner;
+
+impl Scanner for MusicbrainzSearchScanner {
+    async fn scan(&self, input: &ScannerInput<'_>) -> eyre::Result<ScannerRes> {
+        musicbrainz_search_scanner(input.tag, input.fp.duration).await
+    }
+}
 
-use super::ScannerRes;
+const PAGE_SIZE: u32 = 15;
 
+/// Search recordings with every available tag.
+/// If no result on the page has score higher than threshold, next page is fetched.
 #[tracing::instrument(skip_all)]
-pub(super) async fn musicbrainz_search_scanner(crr_tag: &Tag) -> Result<ScannerRes, eyre::Report> {
+pub(super) async fn musicbrainz_search_scanner(
+    crr_tag: &Tag,
+    duration: f64,
+) -> Result<ScannerRes, eyre::Report> {
     let mb_client = MusicbrainzClient::new();
 
-    let title = crr_tag.title().ok_or_else(|| eyre!("No title tag"))?;
+    let tolerance = CONFIG.read().release_selector.duration_distance.tolerance;
+    let query = build_query(crr_tag, duration, tolerance)?;
+    debug!("Search query: {}", query);
+
+    let (max_pages, threshold) = {
+        let config = CONFIG.read();
+        (
+            config.search_max_pages.max(1),
+            config.release_selector.threshold,
+        )
+    };
 
-    let query = format!(r#"recording:"{}""#, title);
+    let mut recordings = vec![];
+    for page in 0..max_pages {
+        let res = mb_client
+            .recording_search(&query, PAGE_SIZE, page * PAGE_SIZE)
+            .await?;
+        let fetched = res.offset + res.recordings.len() as u32;
+        recordings.extend(res.recordings);
 
-    let res = mb_client.recording_search(&query).await?;
+        let best_score = rank_releases_and_recordings(recordings.clone(), crr_tag, Some(duration))
+            .first()
+            .map(|m| m.score.total);
+        if best_score.is_some_and(|score| score >= threshold) || fetched >= res.count {
+            break;
+        }
+        info!("No good match in page {}. Fetching next page.", page + 1);
+    }
 
     Ok(ScannerRes {
         log: ScannerInfo::MusicbrainzSearch,
-        recordings: res.recordings,
+        recordings,
+        search_tag: None,
+        acoustid_scores: HashMap::new(),
     })
 }
+
+/// Build lucene query for recording search.
+/// Title is required and other fields only affect ranking.
+/// Duration is searched within `tolerance` seconds.
+fn build_query(crr_tag: &Tag, duration: f64, tolerance: f64) -> eyre::Result<String> {
+    let title = crr_tag.title().ok_or_else(|| eyre!("No title tag"))?;
+
+    let mut query = vec![format!(r#"+recording:"{}""#, escape_lucene(&title))];
+    if let Some(artist) = crr_tag.artist() {
+        query.push(format!(r#"artist:"{}""#, escape_lucene(&artist)));
+    }
+    if let Some(album) = crr_tag.album() {
+        query.push(format!(r#"release:"{}""#, escape_lucene(&album)));
+    }
+    if let Some(track) = crr_tag.track() {
+        query.push(format!("tnum:{}", track));
+    }
+    if duration > 0.0 {
+        let from = ((duration - tolerance).max(0.0) * 1000.0) as u64;
+        let to = ((duration + tolerance) * 1000.0) as u64;
+        query.push(format!("dur:[{} TO {}]", from, to));
+    }
+    if let Some(isrc) = crr_tag.get_string(&ItemKey::Isrc) {
+        query.push(format!("isrc:{}", escape_lucene(isrc)));
+    }
+
+    Ok(query.join(" "))
+}
+
+/// Escape lucene special characters.
+fn escape_lucene(str: &str) -> String {
+    let mut escaped = String::with_capacity(str.len());
+    for c in str.chars() {
+        if matches!(
+            c,
+            '+' | '-'
+                | '&'
+                | '|'
+                | '!'
+                | '('
+                | ')'
+                | '{'
+                | '}'
+                | '['
+                | ']'
+                | '^'
+                | '"'
+                | '~'
+                | '*'
+                | '?'
+                | ':'
+                | '\\'
+                | '/'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use lofty::tag::{Accessor, ItemKey, Tag, TagType};
+
+    #[test]
+    fn escape_lucene() {
+        let cases = [
+            ("title", "title"),
+            ("Re: Stacks", r"Re\: Stacks"),
+            (r#"Say "Hello""#, r#"Say \"Hello\""#),
+            ("Song (Live)", r"Song \(Live\)"),
+            ("Rock && Roll || Not", r"Rock \&\& Roll \|\| Not"),
+            ("AC/DC", r"AC\/DC"),
+            (r"back\slash", r"back\\slash"),
+            ("What?! *-+~^", r"What\?\! \*\-\+\~\^"),
+            ("[a]{b}", r"\[a\]\{b\}"),
+            ("日本語", "日本語"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(super::escape_lucene(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn build_query() {
+        let mut tag = Tag::new(TagType::Id3v2);
+        assert!(super::build_query(&tag, 200.0, 10.0).is_err());
+
+        tag.set_title(r#"Title: "Part 1" (Remix)"#.to_string());
+        assert_eq!(
+            super::build_query(&tag, 0.0, 10.0).unwrap(),
+            r#"+recording:"Title\: \"Part 1\" \(Remix\)""#
+        );
+
+        tag.set_artist("AC/DC".to_string());
+        tag.set_album("Album && More".to_string());
+        tag.set_track(3);
+        tag.insert_text(ItemKey::Isrc, "USABC1234567".to_string());
+        assert_eq!(
+            super::build_query(&tag, 5.5, 10.0).unwrap(),
+            concat!(
+                r#"+recording:"Title\: \"Part 1\" \(Remix\)" "#,
+                r#"artist:"AC\/DC" release:"Album \&\& More" tnum:3 "#,
+                "dur:[0 TO 15500] isrc:USABC1234567"
+            )
+        );
+    }
+}
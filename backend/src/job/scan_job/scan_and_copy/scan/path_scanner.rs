@@ -0,0 +1,76 @@
+use std::{collections::HashMap, path::Path};
+
+use eyre::eyre;
+use lofty::tag::{Accessor, Tag};
+use tracing::{debug, warn};
+
+use crate::{
+    config::CONFIG,
+    job::{scan_job::scan_and_copy::ScannerInfo, utils::FilenamePattern},
+};
+
+use super::{
+    musicbrainz_search_scanner::musicbrainz_search_scanner, Scanner, ScannerInput, ScannerRes,
+};
+
+pub(super) struct PathScanner;
+
+impl Scanner for PathScanner {
+    async fn scan(&self, input: &ScannerInput<'_>) -> eyre::Result<ScannerRes> {
+        path_scanner(input.path, input.tag, input.fp.duration).await
+    }
+}
+
+/// Search musicbrainz with fields extracted from the path by `filename_patterns`.
+/// Fields in the tag are preferred and the path only fills missing ones.
+#[tracing::instrument(skip(crr_tag))]
+pub(super) async fn path_scanner(
+    path: &Path,
+    crr_tag: &Tag,
+    duration: f64,
+) -> Result<ScannerRes, eyre::Report> {
+    let (patterns, source_dir) = {
+        let config = CONFIG.read();
+        (config.filename_patterns.clone(), config.source_dir.clone())
+    };
+    let relative_path = path.strip_prefix(&source_dir).unwrap_or(path);
+
+    let info = patterns
+        .iter()
+        .filter_map(|pattern| match FilenamePattern::parse(pattern) {
+            Ok(pattern) => Some(pattern),
+            Err(e) => {
+                warn!("Invalid filename pattern: {}", e);
+                None
+            }
+        })
+        .find_map(|pattern| pattern.extract(relative_path))
+        .ok_or_else(|| eyre!("No filename pattern matched"))?;
+    debug!("Extracted from path: {:?}", info);
+
+    let mut tag = crr_tag.clone();
+    if let (None, Some(title)) = (tag.title(), info.title) {
+        tag.set_title(title);
+    }
+    if let (None, Some(artist)) = (tag.artist(), info.artist) {
+        tag.set_artist(artist);
+    }
+    if let (None, Some(album)) = (tag.album(), info.album) {
+        tag.set_album(album);
+    }
+    if let (None, Some(track)) = (tag.track(), info.track) {
+        tag.set_track(track);
+    }
+    if let (None, Some(disc)) = (tag.disk(), info.disc) {
+        tag.set_disk(disc);
+    }
+
+    let res = musicbrainz_search_scanner(&tag, duration).await?;
+
+    Ok(ScannerRes {
+        log: ScannerInfo::Path,
+        recordings: res.recordings,
+        search_tag: Some(tag),
+        acoustid_scores: HashMap::new(),
+    })
+}
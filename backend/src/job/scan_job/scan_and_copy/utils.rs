@@ -2,37 +2,57 @@ use lofty::tag::{Accessor, Tag};
 use tracing::warn;
 
 use crate::{
-    api::musicbrainz::recording::{RecordingRes, RecordingResRelease},
-    config::{ReleaseSelector, CONFIG},
+    api::musicbrainz::{
+        recording::{RecordingRes, RecordingResRelease},
+        ArtistCreditVecToString,
+    },
+    config::{DatePreference, ReleaseSelector, CONFIG},
+    interface::candidate::{Candidate, ScoreBreakdown},
 };
 
+use super::ScannerInfo;
+
 pub(super) fn calc_score(
     release: &RecordingResRelease,
     recording: &RecordingRes,
     current_tag: &Tag,
+    duration: Option<f64>,
     release_selector: &ReleaseSelector,
-) -> f64 {
-    let mut score = 0.0;
-    if let Some(country) = &release.country {
-        let idx = release_selector
-            .country
-            .preferred
-            .iter()
-            .position(|item| item == &country.to_uppercase());
-        if let Some(idx) = idx {
-            score += release_selector.country.weight * (1.0 / (idx as f64 + 1.0))
-        }
-    }
-    if let Some(primary_type) = &release.release_group.primary_type {
-        let idx = release_selector
-            .release_group_type
-            .preferred
-            .iter()
-            .position(|item| item == &primary_type.to_lowercase());
-        if let Some(idx) = idx {
-            score += release_selector.release_group_type.weight * (1.0 / (idx as f64 + 1.0))
-        }
-    }
+) -> ScoreBreakdown {
+    let mut score = ScoreBreakdown::default();
+
+    let country_score = release
+        .country
+        .as_ref()
+        .and_then(|country| {
+            release_selector
+                .country
+                .preferred
+                .iter()
+                .position(|item| item == &country.to_uppercase())
+        })
+        .map(|idx| 1.0 / (idx as f64 + 1.0))
+        .unwrap_or(0.0);
+    score.add("country", country_score, release_selector.country.weight);
+
+    let release_group_type_score = release
+        .release_group
+        .primary_type
+        .as_ref()
+        .and_then(|primary_type| {
+            release_selector
+                .release_group_type
+                .preferred
+                .iter()
+                .position(|item| item == &primary_type.to_lowercase())
+        })
+        .map(|idx| 1.0 / (idx as f64 + 1.0))
+        .unwrap_or(0.0);
+    score.add(
+        "release_group_type",
+        release_group_type_score,
+        release_selector.release_group_type.weight,
+    );
 
     let release_title_distance_score = if let Some(album) = current_tag.album() {
         let distance_score = strsim::normalized_levenshtein(&album, &release.release_group.title);
@@ -44,7 +64,11 @@ pub(super) fn calc_score(
     } else {
         0.0
     };
-    score += release_selector.release_title_distance.weight * release_title_distance_score;
+    score.add(
+        "release_title_distance",
+        release_title_distance_score,
+        release_selector.release_title_distance.weight,
+    );
 
     let recording_title_distance_score = if let Some(album) = current_tag.title() {
         let distance_score = strsim::normalized_levenshtein(&album, &recording.title);
@@ -56,60 +80,351 @@ pub(super) fn calc_score(
     } else {
         0.0
     };
-    score += release_selector.recording_title_distance.weight * recording_title_distance_score;
+    score.add(
+        "recording_title_distance",
+        recording_title_distance_score,
+        release_selector.recording_title_distance.weight,
+    );
+
+    let duration_distance_score = match (duration, recording.length) {
+        (Some(duration), Some(length)) if release_selector.duration_distance.tolerance > 0.0 => {
+            let diff = (duration - length as f64 / 1000.0).abs();
+            1.0 - (diff / release_selector.duration_distance.tolerance).min(1.0)
+        }
+        _ => 0.0,
+    };
+    score.add(
+        "duration_distance",
+        duration_distance_score,
+        release_selector.duration_distance.weight,
+    );
+
+    let status_score = release
+        .status
+        .as_ref()
+        .map(|status| preference_score(&release_selector.status.preferred, status))
+        .unwrap_or(0.0);
+    score.add("status", status_score, release_selector.status.weight);
+
+    let format_score = release
+        .media
+        .iter()
+        .flatten()
+        .filter_map(|media| media.format.as_ref())
+        .map(|format| preference_score(&release_selector.format.preferred, format))
+        .fold(0.0, f64::max);
+    score.add("format", format_score, release_selector.format.weight);
+
+    let penalized = release
+        .release_group
+        .secondary_types
+        .iter()
+        .flatten()
+        .any(|secondary_type| {
+            release_selector
+                .secondary_type_penalty
+                .penalized
+                .iter()
+                .any(|item| item.eq_ignore_ascii_case(secondary_type))
+        });
+    score.add(
+        "secondary_type_penalty",
+        if penalized { -1.0 } else { 0.0 },
+        release_selector.secondary_type_penalty.weight,
+    );
+
+    let date_score = match release_selector.date.prefer {
+        DatePreference::None => 0.0,
+        DatePreference::Earliest => date_match_score(
+            release.date.as_deref(),
+            recording.first_release_date.as_deref(),
+        ),
+        DatePreference::Original => date_match_score(
+            release.date.as_deref(),
+            release.release_group.first_release_date.as_deref(),
+        ),
+    };
+    score.add("date", date_score, release_selector.date.weight);
 
     score
 }
 
-pub(super) fn find_best_release_and_recording(
+/// 1.0 for the first preferred item, 0.5 for the second, and so on. Case insensitive.
+fn preference_score(preferred: &[String], value: &str) -> f64 {
+    preferred
+        .iter()
+        .position(|item| item.eq_ignore_ascii_case(value))
+        .map(|idx| 1.0 / (idx as f64 + 1.0))
+        .unwrap_or(0.0)
+}
+
+/// 1.0 if the date is the same as the first release date, 0.5 if only the year is the same.
+fn date_match_score(date: Option<&str>, first_release_date: Option<&str>) -> f64 {
+    match (date, first_release_date) {
+        (Some(date), Some(first)) if !date.is_empty() && date == first => 1.0,
+        (Some(date), Some(first)) if date.len() >= 4 && first.get(..4) == date.get(..4) => 0.5,
+        _ => 0.0,
+    }
+}
+
+pub(super) struct ScoredMatch {
+    pub recording: RecordingRes,
+    pub release: RecordingResRelease,
+    pub score: ScoreBreakdown,
+}
+
+impl ScoredMatch {
+    pub fn to_candidate(&self) -> Candidate {
+        Candidate {
+            recording_id: self.recording.id.clone(),
+            recording_title: self.recording.title.clone(),
+            release_id: self.release.id.clone(),
+            release_title: self.release.title.clone(),
+            artist: self.recording.artist_credit.as_ref().map(|a| a.to_string()),
+            score: self.score.clone(),
+        }
+    }
+}
+
+/// Calculate score of every release of every recording and sort them by score in descending order.
+/// `duration` is the duration of the file in seconds.
+pub(super) fn rank_releases_and_recordings(
     recordings: Vec<RecordingRes>,
     crr_tag: &Tag,
-) -> Option<(RecordingRes, RecordingResRelease, f64)> {
+    duration: Option<f64>,
+) -> Vec<ScoredMatch> {
     let release_selector = &CONFIG.read().release_selector.clone();
-    let best_recording_releases: Vec<(RecordingRes, RecordingResRelease, f64)> = recordings
+    let mut matches = recordings
         .into_iter()
-        .map(|recording| {
-            let (best_release, best_score) = recording.releases.iter().flatten().fold(
-                (None, -1.0),
-                |(best, best_score), release| {
-                    let score = calc_score(release, &recording, crr_tag, release_selector);
-                    if score > best_score {
-                        (Some(release), score)
-                    } else {
-                        (best, best_score)
-                    }
-                },
-            );
-            let best_release = best_release.cloned();
-            match best_release {
-                Some(best_release) => Some((recording, best_release, best_score)),
-                None => {
-                    warn!(
-                        "No release found for recording: {} ({})",
-                        recording.title, recording.id
-                    );
-                    None
-                }
+        .flat_map(|recording| {
+            let releases = recording.releases.clone().unwrap_or_default();
+            if releases.is_empty() {
+                warn!(
+                    "No release found for recording: {} ({})",
+                    recording.title, recording.id
+                );
             }
+            releases
+                .into_iter()
+                .map(|release| {
+                    let score =
+                        calc_score(&release, &recording, crr_tag, duration, release_selector);
+                    ScoredMatch {
+                        recording: recording.clone(),
+                        release,
+                        score,
+                    }
+                })
+                .collect::<Vec<_>>()
         })
-        .collect::<Vec<_>>()
-        .into_iter()
-        .flatten()
         .collect::<Vec<_>>();
-    let best_recording = best_recording_releases
-        .into_iter()
-        .max_by(|a, b| a.2.partial_cmp(&b.2).expect("This should not happen."));
-    if let Some((recording, release, score)) = best_recording {
-        if score < CONFIG.read().release_selector.threshold {
+    matches.sort_by(|a, b| {
+        b.score
+            .total
+            .partial_cmp(&a.score.total)
+            .expect("This should not happen.")
+    });
+    matches
+}
+
+/// Match found by a scanner.
+pub(super) struct PoolEntry {
+    pub scanner: ScannerInfo,
+    /// Threshold of the scanner which found this match.
+    pub threshold: f64,
+    pub scored: ScoredMatch,
+}
+
+/// Take the match with the highest score among ones above the threshold of their scanner.
+/// `pool` must be sorted by score in descending order.
+pub(super) fn find_best_release_and_recording(pool: &[PoolEntry]) -> Option<&PoolEntry> {
+    let best = pool
+        .iter()
+        .find(|entry| entry.scored.score.threshold_total() >= entry.threshold);
+    if best.is_none() {
+        if let Some(top) = pool.first() {
             warn!(
                 "Best release score was lower than threshold. Score: {}",
-                score
+                top.scored.score.threshold_total()
             );
-            None
-        } else {
-            Some((recording, release, score))
         }
-    } else {
-        None
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use lofty::tag::{Tag, TagType};
+
+    use crate::{
+        api::musicbrainz::recording::{
+            RecordingRes, RecordingResRelease, RecordingResReleaseGroup,
+        },
+        config::ReleaseSelector,
+    };
+
+    fn recording(length: Option<u32>) -> RecordingRes {
+        RecordingRes {
+            title: "title".to_string(),
+            id: "recording".to_string(),
+            length,
+            releases: None,
+            artist_credit: None,
+            first_release_date: None,
+        }
+    }
+
+    fn release() -> RecordingResRelease {
+        RecordingResRelease {
+            id: "release".to_string(),
+            title: "release".to_string(),
+            country: None,
+            status: None,
+            date: None,
+            media: None,
+            release_group: RecordingResReleaseGroup {
+                id: "release_group".to_string(),
+                title: "release".to_string(),
+                primary_type: None,
+                secondary_types: None,
+                first_release_date: None,
+            },
+        }
+    }
+
+    #[test]
+    fn duration_distance() {
+        // (file duration, recording length in ms, tolerance, expected score)
+        let cases = [
+            (Some(200.0), Some(200_000), 10.0, 1.0),
+            (Some(200.0), Some(205_000), 10.0, 0.5),
+            (Some(200.0), Some(195_000), 10.0, 0.5),
+            (Some(200.0), Some(230_000), 10.0, 0.0),
+            (Some(200.0), Some(200_000), 0.0, 0.0),
+            (Some(200.0), None, 10.0, 0.0),
+            (None, Some(200_000), 10.0, 0.0),
+        ];
+        for (duration, length, tolerance, expected) in cases {
+            let mut selector = ReleaseSelector::default();
+            selector.duration_distance.tolerance = tolerance;
+            selector.duration_distance.weight = 2.0;
+            let score = super::calc_score(
+                &release(),
+                &recording(length),
+                &Tag::new(TagType::Id3v2),
+                duration,
+                &selector,
+            );
+            let component = score
+                .components
+                .iter()
+                .find(|c| c.name == "duration_distance")
+                .unwrap();
+            assert_eq!(
+                component.value, expected,
+                "{:?} {:?} {}",
+                duration, length, tolerance
+            );
+            assert_eq!(component.contribution, expected * 2.0);
+        }
+    }
+
+    #[test]
+    fn duration_distance_disabled_by_default() {
+        let score = super::calc_score(
+            &release(),
+            &recording(Some(200_000)),
+            &Tag::new(TagType::Id3v2),
+            Some(200.0),
+            &ReleaseSelector::default(),
+        );
+        let component = score
+            .components
+            .iter()
+            .find(|c| c.name == "duration_distance")
+            .unwrap();
+        assert_eq!(component.contribution, 0.0);
+    }
+
+    #[test]
+    fn preference_score() {
+        let preferred = ["official".to_string(), "promotion".to_string()];
+        let cases = [
+            ("official", 1.0),
+            ("Official", 1.0),
+            ("promotion", 0.5),
+            ("bootleg", 0.0),
+            ("", 0.0),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(
+                super::preference_score(&preferred, value),
+                expected,
+                "{}",
+                value
+            );
+        }
+        assert_eq!(super::preference_score(&[], "official"), 0.0);
+    }
+
+    #[test]
+    fn date_match_score() {
+        let cases = [
+            (Some("2001-02-03"), Some("2001-02-03"), 1.0),
+            (Some("2001"), Some("2001"), 1.0),
+            (Some("2001-05-01"), Some("2001-02-03"), 0.5),
+            (Some("2001"), Some("2001-02-03"), 0.5),
+            (Some("2002-02-03"), Some("2001-02-03"), 0.0),
+            (Some("20"), Some("2001"), 0.0),
+            (Some(""), Some(""), 0.0),
+            (None, Some("2001"), 0.0),
+            (Some("2001"), None, 0.0),
+        ];
+        for (date, first_release_date, expected) in cases {
+            assert_eq!(
+                super::date_match_score(date, first_release_date),
+                expected,
+                "{:?} {:?}",
+                date,
+                first_release_date
+            );
+        }
+    }
+
+    #[test]
+    fn secondary_type_penalty() {
+        // (secondary types of the release group, expected score)
+        let cases = [
+            (None, 0.0),
+            (Some(vec![]), 0.0),
+            (Some(vec!["Compilation"]), -1.0),
+            (Some(vec!["live"]), -1.0),
+            (Some(vec!["Soundtrack", "Live"]), -1.0),
+            (Some(vec!["Soundtrack"]), 0.0),
+        ];
+        let mut selector = ReleaseSelector::default();
+        selector.secondary_type_penalty.penalized =
+            vec!["compilation".to_string(), "Live".to_string()];
+        selector.secondary_type_penalty.weight = 2.0;
+        for (secondary_types, expected) in cases {
+            let mut release = release();
+            release.release_group.secondary_types = secondary_types
+                .clone()
+                .map(|types| types.into_iter().map(String::from).collect());
+            let score = super::calc_score(
+                &release,
+                &recording(None),
+                &Tag::new(TagType::Id3v2),
+                None,
+                &selector,
+            );
+            let component = score
+                .components
+                .iter()
+                .find(|c| c.name == "secondary_type_penalty")
+                .unwrap();
+            assert_eq!(component.value, expected, "{:?}", secondary_types);
+            assert_eq!(component.contribution, expected * 2.0);
+        }
     }
 }
@@ -1,13 +1,29 @@
 use std::path::{Path, PathBuf};
 
 use crate::{
-    api::musicbrainz::{recording::RecordingRes, release::ReleaseRes, ArtistCreditVecToString},
-    interface::metadata::Metadata,
+    api::musicbrainz::{
+        recording::RecordingRes, release::ReleaseRes, ArtistCreditVecToString, MusicbrainzClient,
+    },
+    interface::metadata::{remove_metadata, write_metadata, Metadata},
 };
 use eyre::{eyre, Context, Result};
-use lofty::{file::TaggedFileExt as _, picture::Picture, read_from_path, tag::Tag};
+use lofty::{
+    config::WriteOptions,
+    file::TaggedFileExt as _,
+    read_from_path,
+    tag::{Tag, TagExt as _},
+};
 use sanitize_filename::sanitize;
-use tracing::warn;
+use tracing::{info, warn};
+
+pub(super) use self::cover_art::CoverArt;
+pub use self::filename_pattern::FilenamePattern;
+pub use self::path_template::PathTemplate;
+
+mod cover_art;
+mod filename_pattern;
+pub(super) mod library;
+mod path_template;
 
 /// Collect data, and format it into a metadata struct.
 pub(super) fn response_to_metadata(
@@ -79,18 +95,25 @@ pub(super) fn response_to_metadata(
     Ok(metadata)
 }
 
-#[tracing::instrument]
-pub(super) async fn fetch_cover_art(release_id: &str) -> eyre::Result<Picture> {
-    let cover_art = reqwest::get(&format!(
-        "https://coverartarchive.org/release/{}/front",
-        release_id
-    ))
-    .await?
-    .bytes()
-    .await?;
-    let mut cover_art = std::io::Cursor::new(cover_art);
-    let picture = Picture::from_reader(&mut cover_art)?;
-    Ok(picture)
+/// Rewrite the tag with the current musicbrainz data of the ids in `metadata`.
+/// All fields written by tagbrain are removed first, so fields which were deleted in musicbrainz
+/// don't remain in the file. Returns the new metadata.
+pub(super) async fn refresh_tag(tag: &mut Tag, metadata: &Metadata) -> Result<Metadata> {
+    let (Some(release_id), Some(recording_id)) = (
+        &metadata.musicbrainz_release_id,
+        &metadata.musicbrainz_recording_id,
+    ) else {
+        return Err(eyre!("File doesn't have musicbrainz ids"));
+    };
+
+    let mb_client = MusicbrainzClient::new();
+    let release = mb_client.release(release_id).await?;
+    let recording = mb_client.recording(recording_id).await?;
+    let new_metadata = response_to_metadata(recording, release)?;
+
+    remove_metadata(tag);
+    write_metadata(tag, new_metadata.clone());
+    Ok(new_metadata)
 }
 
 /// Read tag from file. If file has no tag, return default tag.
@@ -113,68 +136,287 @@ pub(super) fn read_tag_or_default(path: &Path) -> eyre::Result<Tag> {
     Ok(tag)
 }
 
-/// Determine the save path from metadata and source path (for detecting extension).
+/// Determine the save path from metadata, path template and source path (for detecting extension).
 pub(super) fn get_save_path_from_metadata(
     source_path: &Path,
     target_dir: &Path,
+    template: &str,
     metadata: &Metadata,
 ) -> eyre::Result<PathBuf> {
     let Some(Some(ext)) = source_path.extension().map(|ext| ext.to_str()) else {
         return Err(eyre!("No extension found!"));
     };
 
+    let mut components = PathTemplate::parse(template)?.render(metadata)?;
+    if let Some(file_name) = components.last_mut() {
+        file_name.push('.');
+        file_name.push_str(ext);
+    }
+
     let mut new_path = PathBuf::new();
+    new_path.push(target_dir);
+    for component in components {
+        new_path.push(sanitize(component));
+    }
+    Ok(new_path)
+}
+
+/// Determine the path of the file tagged in place.
+/// If `rename` is true, the file name is rendered from the last component of the path template.
+pub(super) fn get_in_place_path_from_metadata(
+    source_path: &Path,
+    template: &str,
+    metadata: &Metadata,
+    rename: bool,
+) -> eyre::Result<PathBuf> {
+    if !rename {
+        return Ok(source_path.to_path_buf());
+    }
+    let save_path = get_save_path_from_metadata(source_path, Path::new(""), template, metadata)?;
+    let file_name = save_path
+        .file_name()
+        .ok_or_else(|| eyre!("Path template rendered no file name"))?;
+    Ok(source_path.with_file_name(file_name))
+}
 
-    let artist = metadata
-        .artist
-        .as_ref()
-        .ok_or_else(|| eyre!("artist not found"))?;
-    let album = metadata
-        .album
-        .as_ref()
-        .ok_or_else(|| eyre!("album not found"))?;
-    let title = metadata
-        .title
-        .as_ref()
-        .ok_or_else(|| eyre!("title not found"))?;
-    let album_artist = &metadata.album_artist;
-    let track = &metadata.track;
+const TEMP_FILE_PREFIX: &str = ".tagbrain-";
 
-    new_path.push(target_dir);
-    new_path.push(sanitize(album_artist.clone().unwrap_or(artist.clone())));
-    new_path.push(sanitize(album.clone()));
-    if let (Some(total_discs), Some(disc)) = (metadata.total_discs, metadata.disc) {
-        if total_discs > 1 {
-            let width = total_discs.to_string().len();
-            let disc = format!("{:0width$}", disc, width = width);
-            new_path.push(sanitize(format!("Disc {}", disc)));
+/// Hidden path next to `path` where the file is staged before it's renamed into place.
+/// The extension is kept so that the file type can be detected while writing tags.
+pub(super) fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!("{}{}", TEMP_FILE_PREFIX, file_name))
+}
+
+/// Remove temp files left by a process which died while saving.
+/// Staged files are always copies, so the original files are never lost.
+pub(super) async fn remove_temp_files(dir: &Path) {
+    let temp_files = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .flatten()
+        .filter(|item| item.file_type().is_file())
+        .filter(|item| {
+            item.file_name()
+                .to_string_lossy()
+                .starts_with(TEMP_FILE_PREFIX)
+        })
+        .map(|item| item.path().to_path_buf())
+        .collect::<Vec<_>>();
+    for path in temp_files {
+        info!("Removing temp file: {}", path.display());
+        if let Err(e) = tokio::fs::remove_file(&path).await {
+            warn!("Failed to remove temp file {}: {}", path.display(), e);
         }
     }
-    new_path.push(sanitize({
-        let mut file_name = String::new();
-        if let Some(track) = track {
-            let width = metadata.total_tracks.unwrap_or(0).to_string().len();
-            let track = format!("{:0width$}", track, width = width);
-            file_name.push_str(&track);
-            file_name.push_str(" - ");
+}
+
+/// Write tag to the file and read it back to check that the file is not broken.
+pub(super) fn write_tag_verified(path: &Path, tag: &Tag) -> eyre::Result<()> {
+    tag.save_to_path(path, WriteOptions::new())
+        .wrap_err("Failed to write tag")?;
+
+    let written = read_tag_or_default(path).wrap_err("Failed to read written tag")?;
+    let expected = Metadata::from_tag(tag);
+    let actual = Metadata::from_tag(&written);
+    if actual.title != expected.title
+        || actual.musicbrainz_release_id != expected.musicbrainz_release_id
+        || actual.musicbrainz_recording_id != expected.musicbrainz_recording_id
+    {
+        return Err(eyre!("Written tag doesn't match: {}", path.display()));
+    }
+    Ok(())
+}
+
+/// Copy the file to a hidden temp file next to `target_path`, write tag to it
+/// and rename it into place after the tag is verified.
+/// The target path never has an untagged or half-written file even if the process dies.
+pub(super) async fn copy_file_with_tag(
+    source_path: &Path,
+    target_path: &Path,
+    tag: &Tag,
+) -> eyre::Result<()> {
+    copy_staged(source_path, target_path, |path| {
+        write_tag_verified(path, tag)
+    })
+    .await
+}
+
+/// Move the file to `target_path` and write tag to it.
+/// `source_path` and `target_path` may be the same path to rewrite tag in place.
+/// The file is tagged as in `copy_file_with_tag`, and the source is removed only after the tagged
+/// copy is in place, so the original audio is never lost even if the process dies.
+pub(super) async fn move_file_with_tag(
+    source_path: &Path,
+    target_path: &Path,
+    tag: &Tag,
+) -> eyre::Result<()> {
+    move_staged(source_path, target_path, |path| {
+        write_tag_verified(path, tag)
+    })
+    .await
+}
+
+async fn move_staged(
+    source_path: &Path,
+    target_path: &Path,
+    write: impl FnOnce(&Path) -> eyre::Result<()>,
+) -> eyre::Result<()> {
+    copy_staged(source_path, target_path, write).await?;
+    if source_path != target_path {
+        if let Err(e) = tokio::fs::remove_file(source_path).await {
+            warn!("Failed to delete original file: {}", e);
         }
-        file_name.push_str(title);
-        file_name.push('.');
-        file_name.push_str(ext);
-        file_name
-    }));
-    Ok(new_path)
+    }
+    Ok(())
+}
+
+async fn copy_staged(
+    source_path: &Path,
+    target_path: &Path,
+    write: impl FnOnce(&Path) -> eyre::Result<()>,
+) -> eyre::Result<()> {
+    let temp_path = temp_path_for(target_path);
+    let res = async {
+        tokio::fs::copy(source_path, &temp_path).await?;
+        write(&temp_path)?;
+        tokio::fs::File::open(&temp_path).await?.sync_all().await?;
+        tokio::fs::rename(&temp_path, target_path).await?;
+        eyre::Ok(())
+    }
+    .await;
+    if res.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+    res
+}
+
+/// Move file. If rename fails (e.g. across filesystems), copy and remove instead.
+pub(super) async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if tokio::fs::rename(from, to).await.is_ok() {
+        return Ok(());
+    }
+    tokio::fs::copy(from, to).await?;
+    tokio::fs::remove_file(from).await
+}
+
+/// Remove `dir` and its parents while they are empty, stopping at `root`.
+pub(super) async fn remove_empty_dirs(dir: &Path, root: &Path) {
+    let mut dir = dir;
+    while dir.starts_with(root) && dir != root {
+        if tokio::fs::remove_dir(dir).await.is_err() {
+            break;
+        }
+        match dir.parent() {
+            Some(parent) => dir = parent,
+            None => break,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use std::path::PathBuf;
 
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tagbrain-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
     #[tokio::test]
-    async fn cover_art() {
-        let cover_art = super::fetch_cover_art("db85c244-53e7-441c-bab0-52c9c0d27450")
+    async fn move_staged() {
+        let dir = test_dir("move_staged");
+        let source = dir.join("source.flac");
+        let target = dir.join("target.flac");
+        std::fs::write(&source, b"audio").unwrap();
+
+        super::move_staged(&source, &target, |_| Ok(()))
             .await
             .unwrap();
+
+        assert!(!source.exists());
+        assert_eq!(std::fs::read(&target).unwrap(), b"audio");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn move_staged_in_place() {
+        let dir = test_dir("move_staged_in_place");
+        let path = dir.join("file.flac");
+        std::fs::write(&path, b"audio").unwrap();
+
+        super::move_staged(&path, &path, |temp| {
+            std::fs::write(temp, b"tagged")?;
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"tagged");
+        assert!(!super::temp_path_for(&path).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn move_staged_keeps_source() {
+        let dir = test_dir("move_staged_keeps_source");
+        let source = dir.join("source.flac");
+        let target = dir.join("target.flac");
+        std::fs::write(&source, b"audio").unwrap();
+        // Renaming a file onto a non-empty directory fails.
+        std::fs::create_dir_all(target.join("dir")).unwrap();
+
+        let res = super::move_staged(&source, &target, |_| Ok(())).await;
+
+        assert!(res.is_err());
+        assert_eq!(std::fs::read(&source).unwrap(), b"audio");
+        assert!(!super::temp_path_for(&target).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn move_staged_in_place_keeps_file() {
+        let dir = test_dir("move_staged_in_place_keeps_file");
+        let path = dir.join("file.flac");
+        std::fs::write(&path, b"audio").unwrap();
+
+        let res = super::move_staged(&path, &path, |temp| {
+            // The original file must stay untouched while the copy is written.
+            assert_eq!(std::fs::read(&path)?, b"audio");
+            std::fs::write(temp, b"broken")?;
+            Err(eyre::eyre!("Written tag doesn't match"))
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"audio");
+        assert!(!super::temp_path_for(&path).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn remove_temp_files() {
+        let dir = test_dir("remove_temp_files");
+        let path = dir.join("album").join("file.flac");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"audio").unwrap();
+        std::fs::write(super::temp_path_for(&path), b"audio").unwrap();
+
+        super::remove_temp_files(&dir).await;
+
+        assert!(path.exists());
+        assert!(!super::temp_path_for(&path).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cover_art() {
+        let cover_art = super::CoverArt::new("db85c244-53e7-441c-bab0-52c9c0d27450", None);
+        let cover_art = cover_art.front().await.unwrap();
         assert_eq!(
             cover_art.mime_type().map(|s| s.to_string()),
             Some("image/jpeg".to_string())
@@ -183,15 +425,7 @@ mod test {
 
     #[test]
     fn save_path_1() {
-        let get_path = |metadata: &super::Metadata| {
-            super::get_save_path_from_metadata(
-                &std::path::PathBuf::from("source_path.mp3"),
-                &PathBuf::from("/target_dir"),
-                metadata,
-            )
-            .unwrap()
-        };
-        let mut metadata = super::Metadata {
+        let metadata = super::Metadata {
             title: Some("title".to_string()),
             artist: Some("artist".to_string()),
             artist_sort: Some("artist_sort".to_string()),
@@ -202,7 +436,7 @@ mod test {
             total_tracks: Some(2),
             disc: Some(1),
             total_discs: Some(2),
-            original_date: Some("original_date".to_string()),
+            original_date: Some("2001-02-03".to_string()),
             date: Some("date".to_string()),
             year: Some("year".to_string()),
             label: Some("label".to_string()),
@@ -215,27 +449,137 @@ mod test {
             musicbrainz_release_group_id: Some("musicbrainz_release_group_id".to_string()),
             musicbrainz_recording_id: Some("musicbrainz_recording_id".to_string()),
         };
-        assert_eq!(
-            get_path(&metadata),
-            std::path::PathBuf::from("/target_dir/album_artist/album/Disc 1/1 - title.mp3")
-        );
 
-        metadata.total_tracks = Some(10);
-        assert_eq!(
-            get_path(&metadata),
-            std::path::PathBuf::from("/target_dir/album_artist/album/Disc 1/01 - title.mp3")
-        );
+        let default_template = crate::config::Config::default_path_template();
+        type Case<'a> = (&'a str, fn(&mut super::Metadata), &'a str);
+        let cases: Vec<Case> = vec![
+            (
+                &default_template,
+                |_| {},
+                "/target_dir/album_artist/album/Disc 1/1 - title.mp3",
+            ),
+            (
+                &default_template,
+                |m| m.total_tracks = Some(10),
+                "/target_dir/album_artist/album/Disc 1/01 - title.mp3",
+            ),
+            (
+                &default_template,
+                |m| {
+                    m.total_tracks = Some(10);
+                    m.total_discs = None;
+                },
+                "/target_dir/album_artist/album/01 - title.mp3",
+            ),
+            (
+                &default_template,
+                |m| {
+                    m.total_tracks = Some(10);
+                    m.total_discs = Some(10);
+                },
+                "/target_dir/album_artist/album/Disc 01/01 - title.mp3",
+            ),
+            (
+                &default_template,
+                |m| m.track = None,
+                "/target_dir/album_artist/album/Disc 1/title.mp3",
+            ),
+            (
+                &default_template,
+                |m| m.album_artist = None,
+                "/target_dir/artist/album/Disc 1/1 - title.mp3",
+            ),
+            (
+                "{album_artist}/{year} - {album}/{disc:02}-{track:02} {title}",
+                |_| {},
+                "/target_dir/album_artist/year - album/01-01 title.mp3",
+            ),
+            (
+                "{artist}/{original_year} - {album}/{track:03} {title}",
+                |_| {},
+                "/target_dir/artist/2001 - album/001 title.mp3",
+            ),
+            (
+                "{label|\"Unknown Label\"}/{title}",
+                |m| m.label = None,
+                "/target_dir/Unknown Label/title.mp3",
+            ),
+            (
+                "[?compilation:Compilations/{album}][!compilation:{album_artist}/{album}]/{title}",
+                |_| {},
+                "/target_dir/album_artist/album/title.mp3",
+            ),
+            (
+                "[?compilation:Compilations/{album}][!compilation:{album_artist}/{album}]/{title}",
+                |m| {
+                    m.musicbrainz_release_artist_id =
+                        Some("89ad4ac3-39f7-470e-963a-56509c546377".to_string())
+                },
+                "/target_dir/Compilations/album/title.mp3",
+            ),
+            (
+                "{album_artist}/{album}/[{media} ]{title}",
+                |m| m.media = None,
+                "/target_dir/album_artist/album/title.mp3",
+            ),
+            (
+                "{album_artist}/{album}/{title} \\[{year}\\]",
+                |_| {},
+                "/target_dir/album_artist/album/title [year].mp3",
+            ),
+            (
+                "{album_artist}/{album}/{title}",
+                |m| m.title = Some("a/b:c".to_string()),
+                "/target_dir/album_artist/album/abc.mp3",
+            ),
+        ];
 
-        metadata.total_discs = None;
-        assert_eq!(
-            get_path(&metadata),
-            std::path::PathBuf::from("/target_dir/album_artist/album/01 - title.mp3")
-        );
+        for (template, modify, expected) in cases {
+            let mut metadata = metadata.clone();
+            modify(&mut metadata);
+            assert_eq!(
+                super::get_save_path_from_metadata(
+                    &PathBuf::from("source_path.mp3"),
+                    &PathBuf::from("/target_dir"),
+                    template,
+                    &metadata,
+                )
+                .unwrap(),
+                PathBuf::from(expected),
+                "template: {}",
+                template
+            );
+        }
 
-        metadata.total_discs = Some(10);
-        assert_eq!(
-            get_path(&metadata),
-            std::path::PathBuf::from("/target_dir/album_artist/album/Disc 01/01 - title.mp3")
-        );
+        let invalid_templates = [
+            "{unknown}",
+            "{title",
+            "[{title}",
+            "{track:x}",
+            "[?foo:{title}]",
+        ];
+        for template in invalid_templates {
+            assert!(
+                super::get_save_path_from_metadata(
+                    &PathBuf::from("source_path.mp3"),
+                    &PathBuf::from("/target_dir"),
+                    template,
+                    &metadata,
+                )
+                .is_err(),
+                "template: {}",
+                template
+            );
+        }
+
+        let mut metadata = metadata.clone();
+        metadata.title = None;
+        assert!(super::get_save_path_from_metadata(
+            &PathBuf::from("source_path.mp3"),
+            &PathBuf::from("/target_dir"),
+            &default_template,
+            &metadata,
+        )
+        .is_err());
     }
 }
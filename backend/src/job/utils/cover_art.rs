@@ -0,0 +1,218 @@
+//! Cover art of a release fetched from cover art archive according to `cover_art` in config.
+
+use std::path::Path;
+
+use lofty::{
+    picture::{Picture, PictureInformation, PictureType},
+    tag::Tag,
+};
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+use crate::{
+    api::coverart::{CoverArtClient, CoverArtEntity},
+    config::{CoverArtConfig, CONFIG},
+    interface::metadata::Metadata,
+    watcher::mark_written,
+};
+
+/// Images of a release. Images are fetched at first use and shared between files of the release.
+pub struct CoverArt {
+    release_id: String,
+    release_group_id: Option<String>,
+    config: CoverArtConfig,
+    front: OnceCell<Option<Picture>>,
+    extra: OnceCell<Vec<Picture>>,
+}
+
+impl CoverArt {
+    pub fn new(release_id: &str, release_group_id: Option<&str>) -> Self {
+        Self {
+            release_id: release_id.to_string(),
+            release_group_id: release_group_id.map(|id| id.to_string()),
+            config: CONFIG.read().cover_art.clone(),
+            front: OnceCell::new(),
+            extra: OnceCell::new(),
+        }
+    }
+
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        let release_id = metadata.musicbrainz_release_id.as_deref()?;
+        Some(Self::new(
+            release_id,
+            metadata.musicbrainz_release_group_id.as_deref(),
+        ))
+    }
+
+    /// Embed images into the tag.
+    /// Front cover is added if the tag has none or the existing one is narrower than `replace_below_width`.
+    /// Pictures of other types are never replaced.
+    pub async fn embed(&self, tag: &mut Tag) {
+        if !self.config.embed {
+            return;
+        }
+
+        let existing_front = tag
+            .pictures()
+            .iter()
+            .position(|p| p.pic_type() == PictureType::CoverFront);
+        let replace_front = match existing_front {
+            None => true,
+            Some(idx) => self.config.replace_below_width.is_some_and(|min_width| {
+                PictureInformation::from_picture(&tag.pictures()[idx])
+                    .is_ok_and(|info| info.width < min_width)
+            }),
+        };
+        if replace_front {
+            if let Some(front) = self.front().await {
+                match existing_front {
+                    Some(idx) => tag.set_picture(idx, front.clone()),
+                    None => tag.push_picture(front.clone()),
+                }
+            }
+        }
+
+        let existing_types = tag
+            .pictures()
+            .iter()
+            .map(|p| p.pic_type())
+            .collect::<Vec<_>>();
+        for picture in self.extra().await {
+            if !existing_types.contains(&picture.pic_type()) {
+                tag.push_picture(picture.clone());
+            }
+        }
+    }
+
+    /// Save front cover to `cover.jpg` in the directory unless it already has one.
+    pub async fn write_folder_image(&self, dir: &Path) {
+        if !self.config.folder_image {
+            return;
+        }
+        for name in ["cover.jpg", "cover.png"] {
+            if tokio::fs::try_exists(dir.join(name)).await.unwrap_or(true) {
+                return;
+            }
+        }
+        let Some(front) = self.front().await else {
+            return;
+        };
+        let ext = front
+            .mime_type()
+            .and_then(|mime| mime.ext())
+            .filter(|ext| *ext == "png")
+            .unwrap_or("jpg");
+        let path = dir.join(format!("cover.{}", ext));
+        mark_written(&path);
+        match tokio::fs::write(&path, front.data()).await {
+            Ok(()) => info!("Saved cover art: {}", path.display()),
+            Err(e) => warn!("Failed to save cover art: {}", e),
+        }
+    }
+
+    /// Front cover of the release, or of the release group if the release has none.
+    pub async fn front(&self) -> Option<&Picture> {
+        self.front
+            .get_or_init(|| async {
+                let client = CoverArtClient::new();
+                let size = self.config.thumbnail_size();
+                let mut data = client
+                    .front(CoverArtEntity::Release, &self.release_id, size)
+                    .await;
+                if let (Err(e), Some(release_group_id), true) = (
+                    &data,
+                    &self.release_group_id,
+                    self.config.release_group_fallback,
+                ) {
+                    info!("No cover art of release. Trying release group: {}", e);
+                    data = client
+                        .front(CoverArtEntity::ReleaseGroup, release_group_id, size)
+                        .await;
+                }
+                let picture = data.and_then(|data| {
+                    let mut picture = Picture::from_reader(&mut std::io::Cursor::new(data))?;
+                    picture.set_pic_type(PictureType::CoverFront);
+                    Ok(picture)
+                });
+                match picture {
+                    Ok(picture) => Some(picture),
+                    Err(e) => {
+                        warn!("Failed to fetch cover art: {}", e);
+                        None
+                    }
+                }
+            })
+            .await
+            .as_ref()
+    }
+
+    /// Images of `extra_types` of the release.
+    async fn extra(&self) -> &[Picture] {
+        self.extra
+            .get_or_init(|| async {
+                if self.config.extra_types.is_empty() {
+                    return vec![];
+                }
+                let client = CoverArtClient::new();
+                let images = match client.images(&self.release_id).await {
+                    Ok(res) => res.images,
+                    Err(e) => {
+                        warn!("Failed to fetch list of cover art: {}", e);
+                        return vec![];
+                    }
+                };
+
+                let mut pictures = vec![];
+                for image in images {
+                    let Some(r#type) = image.types.iter().find(|t| {
+                        self.config
+                            .extra_types
+                            .iter()
+                            .any(|extra| extra.eq_ignore_ascii_case(t))
+                    }) else {
+                        continue;
+                    };
+                    let thumbnails = &image.thumbnails;
+                    let url = match self.config.thumbnail_size() {
+                        // Largest available thumbnail within the size. Original image is never used
+                        // because it may be larger than `max_size`.
+                        Some(size) => [
+                            (1200, &thumbnails.large),
+                            (500, &thumbnails.medium),
+                            (250, &thumbnails.small),
+                        ]
+                        .into_iter()
+                        .filter(|(width, _)| *width <= size)
+                        .find_map(|(_, url)| url.as_ref()),
+                        None => Some(&image.image),
+                    };
+                    let Some(url) = url else {
+                        warn!("No thumbnail of cover art {}", image.image);
+                        continue;
+                    };
+                    let picture = client.image(url).await.and_then(|data| {
+                        let mut picture = Picture::from_reader(&mut std::io::Cursor::new(data))?;
+                        picture.set_pic_type(picture_type(r#type));
+                        Ok(picture)
+                    });
+                    match picture {
+                        Ok(picture) => pictures.push(picture),
+                        Err(e) => warn!("Failed to fetch cover art {}: {}", url, e),
+                    }
+                }
+                pictures
+            })
+            .await
+    }
+}
+
+/// Convert image type of cover art archive to picture type.
+fn picture_type(r#type: &str) -> PictureType {
+    match r#type.to_ascii_lowercase().as_str() {
+        "front" => PictureType::CoverFront,
+        "back" => PictureType::CoverBack,
+        "booklet" => PictureType::Leaflet,
+        "medium" => PictureType::Media,
+        _ => PictureType::Other,
+    }
+}
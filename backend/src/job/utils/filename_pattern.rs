@@ -0,0 +1,200 @@
+//! Patterns to extract tag fields from the path of files which have no tags.
+//!
+//! - `{field}` matches any text in a path component. Available fields are `artist`, `album`,
+//!   `title`, `track` and `disc`. `track` and `disc` only match digits.
+//! - `{_}` matches any text, which is ignored.
+//! - `/` separates path components. Pattern is matched against the last components of the path,
+//!   and the extension of the file is removed before matching.
+//! - Other text must match as is.
+
+use std::path::Path;
+
+use eyre::{eyre, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Artist,
+    Album,
+    Title,
+    Track,
+    Disc,
+    Ignore,
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "artist" => Field::Artist,
+            "album" => Field::Album,
+            "title" => Field::Title,
+            "track" => Field::Track,
+            "disc" => Field::Disc,
+            "_" => Field::Ignore,
+            _ => return None,
+        })
+    }
+    fn is_numeric(self) -> bool {
+        matches!(self, Field::Track | Field::Disc)
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Literal(String),
+    Field(Field),
+}
+
+/// Fields extracted from the path.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PathInfo {
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub title: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct FilenamePattern {
+    /// Tokens of each path component
+    components: Vec<Vec<Token>>,
+}
+
+impl FilenamePattern {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let components = pattern
+            .split('/')
+            .map(parse_component)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { components })
+    }
+
+    /// Match the pattern against the end of the path. Returns None if the path doesn't match.
+    pub fn extract(&self, path: &Path) -> Option<PathInfo> {
+        let path = path.with_extension("");
+        let path_components = path
+            .components()
+            .filter_map(|c| c.as_os_str().to_str())
+            .collect::<Vec<_>>();
+        if path_components.len() < self.components.len() {
+            return None;
+        }
+        let path_components = &path_components[path_components.len() - self.components.len()..];
+
+        let mut info = PathInfo::default();
+        for (tokens, text) in self.components.iter().zip(path_components) {
+            for (field, value) in match_tokens(tokens, text)? {
+                let value = value.trim();
+                match field {
+                    Field::Artist => info.artist = Some(value.to_string()),
+                    Field::Album => info.album = Some(value.to_string()),
+                    Field::Title => info.title = Some(value.to_string()),
+                    Field::Track => info.track = value.parse().ok(),
+                    Field::Disc => info.disc = value.parse().ok(),
+                    Field::Ignore => {}
+                }
+            }
+        }
+        Some(info)
+    }
+}
+
+fn parse_component(component: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut literal = String::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => {
+                            return Err(eyre!("Unclosed '{{' in filename pattern: {}", component))
+                        }
+                    }
+                }
+                let field = Field::from_name(&name)
+                    .ok_or_else(|| eyre!("Unknown field in filename pattern: {}", name))?;
+                if matches!(tokens.last(), Some(Token::Field(_))) && literal.is_empty() {
+                    return Err(eyre!(
+                        "Fields must be separated by text in filename pattern: {}",
+                        component
+                    ));
+                }
+                if !literal.is_empty() {
+                    tokens.push(Token::Literal(std::mem::take(&mut literal)));
+                }
+                tokens.push(Token::Field(field));
+            }
+            '}' => return Err(eyre!("Unexpected '}}' in filename pattern: {}", component)),
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+/// Match tokens against the whole text. Fields match the shortest text possible.
+fn match_tokens<'a>(tokens: &[Token], text: &'a str) -> Option<Vec<(Field, &'a str)>> {
+    let Some((first, rest)) = tokens.split_first() else {
+        return text.is_empty().then(Vec::new);
+    };
+    match first {
+        Token::Literal(literal) => match_tokens(rest, text.strip_prefix(literal.as_str())?),
+        Token::Field(field) => {
+            for (end, c) in text.char_indices() {
+                if field.is_numeric() && !c.is_ascii_digit() {
+                    break;
+                }
+                let end = end + c.len_utf8();
+                if let Some(mut matched) = match_tokens(rest, &text[end..]) {
+                    matched.insert(0, (*field, &text[..end]));
+                    return Some(matched);
+                }
+            }
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{FilenamePattern, PathInfo};
+
+    #[test]
+    fn filename_pattern() {
+        let pattern = FilenamePattern::parse("{artist} - {album}/{track} {title}").unwrap();
+        assert_eq!(
+            pattern.extract(Path::new("/music/Artist - A - B/01 Title (Live).flac")),
+            Some(PathInfo {
+                artist: Some("Artist".to_string()),
+                album: Some("A - B".to_string()),
+                title: Some("Title (Live)".to_string()),
+                track: Some(1),
+                disc: None,
+            })
+        );
+        assert_eq!(
+            pattern.extract(Path::new("Artist - Album/Title.flac")),
+            None
+        );
+        assert_eq!(pattern.extract(Path::new("01 Title.flac")), None);
+    }
+
+    #[test]
+    fn invalid_pattern() {
+        assert!(FilenamePattern::parse("{unknown}").is_err());
+        assert!(FilenamePattern::parse("{artist}{title}").is_err());
+        assert!(FilenamePattern::parse("{artist").is_err());
+        assert!(FilenamePattern::parse("{artist} - {title").is_err());
+        assert!(FilenamePattern::parse("{artist}/{title").is_err());
+        assert!(FilenamePattern::parse("artist}").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+//! Musicbrainz ids of files saved by jobs, used to find duplicates of a new file.
+
+use std::path::{Path, PathBuf};
+
+use sqlx::query;
+use tracing::warn;
+
+use crate::{interface::metadata::Metadata, POOL};
+
+/// Record ids of the file saved at `path`. Files without ids are forgotten.
+pub async fn record(path: &Path, metadata: &Metadata) {
+    let (Some(recording_id), Some(release_id)) = (
+        &metadata.musicbrainz_recording_id,
+        &metadata.musicbrainz_release_id,
+    ) else {
+        remove(path).await;
+        return;
+    };
+    let path_str = path.to_string_lossy();
+    let res = query!(
+        "INSERT OR REPLACE INTO library_file (path, recording_id, release_id) VALUES (?, ?, ?)",
+        path_str,
+        recording_id,
+        release_id
+    )
+    .execute(&*POOL)
+    .await;
+    if let Err(e) = res {
+        warn!("Failed to record library file {}: {:?}", path.display(), e);
+    }
+}
+
+/// Forget the file which was removed or moved.
+pub async fn remove(path: &Path) {
+    let path_str = path.to_string_lossy();
+    let res = query!("DELETE FROM library_file WHERE path = ?", path_str)
+        .execute(&*POOL)
+        .await;
+    if let Err(e) = res {
+        warn!("Failed to remove library file {}: {:?}", path.display(), e);
+    }
+}
+
+/// Paths of files recorded with the ids. They may have been changed or removed since.
+pub async fn find(recording_id: &str, release_id: &str) -> Result<Vec<PathBuf>, sqlx::Error> {
+    let paths = query!(
+        "SELECT path FROM library_file WHERE recording_id = ? AND release_id = ?",
+        recording_id,
+        release_id
+    )
+    .fetch_all(&*POOL)
+    .await?
+    .into_iter()
+    .map(|row| PathBuf::from(row.path))
+    .collect();
+    Ok(paths)
+}
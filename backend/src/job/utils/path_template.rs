@@ -0,0 +1,354 @@
+//! Template language used to build the save path of files.
+//!
+//! - `{field}` is replaced with the value of the metadata field.
+//! - `{field1|field2|"literal"}` uses the first field that has a value. Quoted strings are literals.
+//! - `{track:02}` pads number with zeros to the given width. `{track:auto}` pads to the width of
+//!   the total (`total_tracks` for `track`, `total_discs` for `disc`).
+//! - `[...]` is only rendered if every field inside has a value.
+//! - `[?cond:...]` is only rendered if `cond` is true, `[!cond:...]` if it is false.
+//!   Available conditions are `multidisc` and `compilation`.
+//! - `/` separates path components. Each component is sanitized separately.
+//! - `\` escapes the next character.
+
+use std::{iter::Peekable, str::Chars};
+
+use eyre::{eyre, Result};
+
+use crate::interface::metadata::Metadata;
+
+/// MBID of the "Various Artists" special purpose artist.
+const VARIOUS_ARTISTS_ID: &str = "89ad4ac3-39f7-470e-963a-56509c546377";
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Separator,
+    Field {
+        /// Source text of the candidates. Used for error message.
+        source: String,
+        candidates: Vec<Candidate>,
+        format: Format,
+    },
+    Section {
+        condition: Option<Condition>,
+        nodes: Vec<Node>,
+    },
+}
+
+#[derive(Debug)]
+enum Candidate {
+    Field(Field),
+    Literal(String),
+}
+
+#[derive(Debug)]
+enum Format {
+    None,
+    Pad(usize),
+    Auto,
+}
+
+#[derive(Debug)]
+struct Condition {
+    negate: bool,
+    kind: ConditionKind,
+}
+
+#[derive(Debug)]
+enum ConditionKind {
+    MultiDisc,
+    Compilation,
+}
+
+impl ConditionKind {
+    fn eval(&self, metadata: &Metadata) -> bool {
+        match self {
+            ConditionKind::MultiDisc => metadata.total_discs.is_some_and(|total| total > 1),
+            ConditionKind::Compilation => match &metadata.musicbrainz_release_artist_id {
+                Some(id) => id == VARIOUS_ARTISTS_ID,
+                None => metadata.album_artist.as_deref() == Some("Various Artists"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Title,
+    Artist,
+    ArtistSort,
+    Album,
+    AlbumArtist,
+    AlbumArtistSort,
+    Track,
+    TotalTracks,
+    Disc,
+    TotalDiscs,
+    OriginalDate,
+    OriginalYear,
+    Date,
+    Year,
+    Label,
+    Media,
+    Script,
+}
+
+enum Value {
+    Text(String),
+    Number { value: u32, total: Option<u32> },
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "title" => Field::Title,
+            "artist" => Field::Artist,
+            "artist_sort" => Field::ArtistSort,
+            "album" => Field::Album,
+            "album_artist" => Field::AlbumArtist,
+            "album_artist_sort" => Field::AlbumArtistSort,
+            "track" => Field::Track,
+            "total_tracks" => Field::TotalTracks,
+            "disc" => Field::Disc,
+            "total_discs" => Field::TotalDiscs,
+            "original_date" => Field::OriginalDate,
+            "original_year" => Field::OriginalYear,
+            "date" => Field::Date,
+            "year" => Field::Year,
+            "label" => Field::Label,
+            "media" => Field::Media,
+            "script" => Field::Script,
+            _ => return None,
+        })
+    }
+
+    fn value(&self, metadata: &Metadata) -> Option<Value> {
+        let text = |s: &Option<String>| {
+            s.as_ref()
+                .filter(|s| !s.is_empty())
+                .map(|s| Value::Text(s.clone()))
+        };
+        let number =
+            |n: Option<u32>, total: Option<u32>| n.map(|value| Value::Number { value, total });
+        match self {
+            Field::Title => text(&metadata.title),
+            Field::Artist => text(&metadata.artist),
+            Field::ArtistSort => text(&metadata.artist_sort),
+            Field::Album => text(&metadata.album),
+            Field::AlbumArtist => text(&metadata.album_artist),
+            Field::AlbumArtistSort => text(&metadata.album_artist_sort),
+            Field::Track => number(metadata.track, metadata.total_tracks),
+            Field::TotalTracks => number(metadata.total_tracks, None),
+            Field::Disc => number(metadata.disc, metadata.total_discs),
+            Field::TotalDiscs => number(metadata.total_discs, None),
+            Field::OriginalDate => text(&metadata.original_date),
+            Field::OriginalYear => text(
+                &metadata
+                    .original_date
+                    .as_ref()
+                    .and_then(|d| d.split('-').next().map(|s| s.to_owned())),
+            ),
+            Field::Date => text(&metadata.date),
+            Field::Year => text(&metadata.year),
+            Field::Label => text(&metadata.label),
+            Field::Media => text(&metadata.media),
+            Field::Script => text(&metadata.script),
+        }
+    }
+}
+
+/// Parsed path template.
+#[derive(Debug)]
+pub struct PathTemplate {
+    nodes: Vec<Node>,
+}
+
+impl PathTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut chars = template.chars().peekable();
+        let nodes = parse_nodes(&mut chars, false)?;
+        Ok(Self { nodes })
+    }
+
+    /// Render template into path components. Components are not sanitized.
+    pub fn render(&self, metadata: &Metadata) -> Result<Vec<String>> {
+        let mut components = vec![String::new()];
+        render_nodes(&self.nodes, metadata, &mut components)
+            .map_err(|field| eyre!("{} not found", field))?;
+        let components = components
+            .into_iter()
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>();
+        if components.is_empty() {
+            return Err(eyre!("Path template rendered to empty path"));
+        }
+        Ok(components)
+    }
+}
+
+fn parse_nodes(chars: &mut Peekable<Chars>, in_section: bool) -> Result<Vec<Node>> {
+    let mut nodes = vec![];
+    let mut text = String::new();
+    loop {
+        let Some(c) = chars.next() else {
+            if in_section {
+                return Err(eyre!("Unclosed '[' in path template"));
+            }
+            break;
+        };
+        match c {
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| eyre!("Path template ends with '\\'"))?;
+                text.push(escaped);
+                continue;
+            }
+            ']' if in_section => break,
+            ']' => return Err(eyre!("Unexpected ']' in path template")),
+            '}' => return Err(eyre!("Unexpected '}}' in path template")),
+            _ => {}
+        }
+        if matches!(c, '{' | '[' | '/') && !text.is_empty() {
+            nodes.push(Node::Text(std::mem::take(&mut text)));
+        }
+        match c {
+            '{' => nodes.push(parse_field(chars)?),
+            '[' => nodes.push(parse_section(chars)?),
+            '/' => nodes.push(Node::Separator),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+    Ok(nodes)
+}
+
+fn parse_field(chars: &mut Peekable<Chars>) -> Result<Node> {
+    let mut inner = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => inner.push(c),
+            None => return Err(eyre!("Unclosed '{{' in path template")),
+        }
+    }
+
+    let (names, format) = match inner.rsplit_once(':') {
+        Some((names, spec)) if !spec.ends_with('"') => {
+            let format = if spec == "auto" {
+                Format::Auto
+            } else {
+                Format::Pad(
+                    spec.parse()
+                        .map_err(|_| eyre!("Invalid format '{}' in path template", spec))?,
+                )
+            };
+            (names, format)
+        }
+        _ => (inner.as_str(), Format::None),
+    };
+
+    let candidates = names
+        .split('|')
+        .map(|name| {
+            let name = name.trim();
+            if let Some(literal) = name
+                .strip_prefix('"')
+                .and_then(|name| name.strip_suffix('"'))
+            {
+                Ok(Candidate::Literal(literal.to_string()))
+            } else {
+                Field::from_name(name)
+                    .map(Candidate::Field)
+                    .ok_or_else(|| eyre!("Unknown field '{}' in path template", name))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Node::Field {
+        source: names.to_string(),
+        candidates,
+        format,
+    })
+}
+
+fn parse_section(chars: &mut Peekable<Chars>) -> Result<Node> {
+    let condition = match chars.peek() {
+        Some(&c @ ('?' | '!')) => {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some(':') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(eyre!("Unclosed '[' in path template")),
+                }
+            }
+            let kind = match name.as_str() {
+                "multidisc" => ConditionKind::MultiDisc,
+                "compilation" => ConditionKind::Compilation,
+                _ => return Err(eyre!("Unknown condition '{}' in path template", name)),
+            };
+            Some(Condition {
+                negate: c == '!',
+                kind,
+            })
+        }
+        _ => None,
+    };
+    let nodes = parse_nodes(chars, true)?;
+    Ok(Node::Section { condition, nodes })
+}
+
+/// On error, returns name of the missing field.
+fn render_nodes(
+    nodes: &[Node],
+    metadata: &Metadata,
+    components: &mut Vec<String>,
+) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => components.last_mut().unwrap().push_str(text),
+            Node::Separator => components.push(String::new()),
+            Node::Field {
+                source,
+                candidates,
+                format,
+            } => {
+                let value = candidates
+                    .iter()
+                    .find_map(|candidate| match candidate {
+                        Candidate::Field(field) => field.value(metadata),
+                        Candidate::Literal(literal) => Some(Value::Text(literal.clone())),
+                    })
+                    .ok_or_else(|| source.clone())?;
+                let value = match (value, format) {
+                    (Value::Text(text), _) => text,
+                    (Value::Number { value, .. }, Format::None) => value.to_string(),
+                    (Value::Number { value, .. }, Format::Pad(width)) => {
+                        format!("{:0width$}", value, width = width)
+                    }
+                    (Value::Number { value, total }, Format::Auto) => {
+                        let width = total.unwrap_or(0).to_string().len();
+                        format!("{:0width$}", value, width = width)
+                    }
+                };
+                components.last_mut().unwrap().push_str(&value);
+            }
+            Node::Section { condition, nodes } => {
+                if let Some(condition) = condition {
+                    if condition.kind.eval(metadata) == condition.negate {
+                        continue;
+                    }
+                }
+                let mut section_components = components.clone();
+                if render_nodes(nodes, metadata, &mut section_components).is_ok() {
+                    *components = section_components;
+                }
+            }
+        }
+    }
+    Ok(())
+}
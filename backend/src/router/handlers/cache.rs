@@ -0,0 +1,40 @@
+use specta::Type;
+
+use crate::{api::cache, router::Error};
+
+use super::AppState;
+
+#[derive(serde::Serialize, Type, Debug)]
+pub struct CacheInfo {
+    pub count: i64,
+    pub expired_count: i64,
+    /// Total size of cached responses in bytes
+    pub size: i64,
+}
+
+#[tracing::instrument(err, skip(_ctx))]
+pub async fn cache_info(_ctx: AppState, _: ()) -> Result<CacheInfo, Error> {
+    let info = cache::info()
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to get cache info: {:?}", e)))?;
+
+    Ok(CacheInfo {
+        count: info.count,
+        expired_count: info.expired_count,
+        size: info.size,
+    })
+}
+
+#[derive(serde::Deserialize, Type, Debug)]
+pub struct CachePurgeRequest {
+    expired_only: bool,
+}
+
+#[tracing::instrument(err, skip(_ctx))]
+pub async fn cache_purge(_ctx: AppState, req: CachePurgeRequest) -> Result<(), Error> {
+    cache::purge(req.expired_only)
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to purge cache: {:?}", e)))?;
+
+    Ok(())
+}
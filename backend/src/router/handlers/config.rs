@@ -1,5 +1,8 @@
+use tracing::warn;
+
 use crate::{
     config::{Config, CONFIG},
+    job::{FilenamePattern, PathTemplate},
     router::Error,
 };
 
@@ -14,6 +17,21 @@ pub async fn config_read(_: AppState, _: ()) -> Result<String, Error> {
 pub async fn config_write(_: AppState, new_config: String) -> Result<(), Error> {
     let new_config: Config = toml::from_str(&new_config)
         .map_err(|e| Error::BadRequest(format!("Failed to deserialize config: {}", e,)))?;
-    *CONFIG.write() = new_config;
+    PathTemplate::parse(&new_config.path_template)
+        .map_err(|e| Error::BadRequest(format!("Invalid path template: {}", e)))?;
+    for pattern in &new_config.filename_patterns {
+        FilenamePattern::parse(pattern)
+            .map_err(|e| Error::BadRequest(format!("Invalid filename pattern: {}", e)))?;
+    }
+    if new_config.acoustid_submit.enabled && new_config.acoustid_submit.user_api_key.is_empty() {
+        return Err(Error::BadRequest(
+            "acoustid_submit.user_api_key is required to submit fingerprints".to_string(),
+        ));
+    }
+    let mut config = CONFIG.write();
+    if new_config.concurrency != config.concurrency {
+        warn!("concurrency is changed. Restart to apply it.");
+    }
+    *config = new_config;
     Ok(())
 }
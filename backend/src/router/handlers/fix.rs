@@ -3,22 +3,51 @@ use std::path::PathBuf;
 use serde::Deserialize;
 use specta::Type;
 
-use crate::router::Error;
+use crate::{router::Error, POOL};
 
 use super::AppState;
 
+/// Resolve release and recording id from candidate id or given ids.
+async fn resolve_ids(
+    candidate_id: Option<i32>,
+    release_id: Option<String>,
+    recording_id: Option<String>,
+) -> Result<(String, String), Error> {
+    if let Some(candidate_id) = candidate_id {
+        let candidate = sqlx::query!(
+            "SELECT release_id, recording_id FROM scan_candidate WHERE id = ?",
+            candidate_id
+        )
+        .fetch_optional(&*POOL)
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to query db: {:?}", e)))?
+        .ok_or_else(|| Error::BadRequest(format!("Candidate not found: {}", candidate_id)))?;
+        return Ok((candidate.release_id, candidate.recording_id));
+    }
+    match (release_id, recording_id) {
+        (Some(release_id), Some(recording_id)) => Ok((release_id, recording_id)),
+        _ => Err(Error::BadRequest(
+            "candidate_id or both release_id and recording_id are required".to_string(),
+        )),
+    }
+}
+
 #[derive(Deserialize, Type)]
 pub struct FixRequest {
     pub target_path: String,
-    pub release_id: String,
-    pub recording_id: String,
+    pub release_id: Option<String>,
+    pub recording_id: Option<String>,
+    /// If set, release and recording of the candidate are used.
+    pub candidate_id: Option<i32>,
 }
 pub async fn fix(ctx: AppState, req: FixRequest) -> Result<(), Error> {
+    let (release_id, recording_id) =
+        resolve_ids(req.candidate_id, req.release_id, req.recording_id).await?;
     ctx.job_sender
         .send(crate::JobCommand::Fix {
             target_path: PathBuf::from(req.target_path),
-            release_id: req.release_id,
-            recording_id: req.recording_id,
+            release_id,
+            recording_id,
         })
         .map_err(|e| {
             Error::Internal(format!(
@@ -32,15 +61,19 @@ pub async fn fix(ctx: AppState, req: FixRequest) -> Result<(), Error> {
 #[derive(Deserialize, Type)]
 pub struct FixFailedRequest {
     pub source_path: String,
-    pub release_id: String,
-    pub recording_id: String,
+    pub release_id: Option<String>,
+    pub recording_id: Option<String>,
+    /// If set, release and recording of the candidate are used.
+    pub candidate_id: Option<i32>,
 }
 pub async fn fix_failed(ctx: AppState, req: FixFailedRequest) -> Result<(), Error> {
+    let (release_id, recording_id) =
+        resolve_ids(req.candidate_id, req.release_id, req.recording_id).await?;
     ctx.job_sender
         .send(crate::JobCommand::FixFailed {
             source_path: PathBuf::from(req.source_path),
-            release_id: req.release_id,
-            recording_id: req.recording_id,
+            release_id,
+            recording_id,
         })
         .map_err(|e| {
             Error::Internal(format!(
@@ -1,11 +1,16 @@
 use crate::JobSender;
 
+pub(super) mod cache;
 pub(super) mod config;
 pub(super) mod fix;
+pub(super) mod proposal_apply;
 pub(super) mod queue_clear;
 pub(super) mod queue_info;
+pub(super) mod refresh_library;
+pub(super) mod revert;
 pub(super) mod scan;
 pub(super) mod scan_all;
+pub(super) mod scan_candidates;
 pub(super) mod scan_log;
 pub(super) mod scan_log_clear;
 
@@ -0,0 +1,22 @@
+use specta::Type;
+
+use crate::router::Error;
+
+use super::AppState;
+
+#[derive(serde::Deserialize, Type)]
+pub struct ProposalApplyRequest {
+    /// Ids of proposal logs to apply.
+    ids: Vec<i32>,
+}
+pub async fn proposal_apply(ctx: AppState, req: ProposalApplyRequest) -> Result<(), Error> {
+    ctx.job_sender
+        .send(crate::JobCommand::ApplyProposals { log_ids: req.ids })
+        .map_err(|e| {
+            Error::Internal(format!(
+                "Internal server error: failed to send job command: {}",
+                e,
+            ))
+        })?;
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use crate::router::Error;
+
+use super::AppState;
+
+pub async fn refresh_library(ctx: AppState, _: ()) -> Result<(), Error> {
+    ctx.job_sender
+        .send(crate::JobCommand::RefreshLibrary)
+        .map_err(|e| {
+            Error::Internal(format!(
+                "Internal server error: failed to send job command: {}",
+                e,
+            ))
+        })?;
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use specta::Type;
+
+use crate::router::Error;
+
+use super::AppState;
+
+#[derive(serde::Deserialize, Type)]
+pub struct RevertRequest {
+    /// Id of scan or fix log to revert.
+    id: i32,
+}
+pub async fn revert(ctx: AppState, req: RevertRequest) -> Result<(), Error> {
+    ctx.job_sender
+        .send(crate::JobCommand::Revert { log_id: req.id })
+        .map_err(|e| {
+            Error::Internal(format!(
+                "Internal server error: failed to send job command: {}",
+                e,
+            ))
+        })?;
+    Ok(())
+}
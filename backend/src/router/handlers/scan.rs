@@ -9,6 +9,8 @@ use super::AppState;
 #[derive(serde::Deserialize, Type)]
 pub struct ScanRequest {
     path: String,
+    /// Overrides `dry_run` in config if specified.
+    dry_run: Option<bool>,
 }
 pub async fn scan(ctx: AppState, req: ScanRequest) -> Result<(), Error> {
     ctx.job_sender
@@ -16,6 +18,7 @@ pub async fn scan(ctx: AppState, req: ScanRequest) -> Result<(), Error> {
             path: std::path::PathBuf::from_str(&req.path)
                 .map_err(|e| Error::BadRequest(format!("Invalid path: {}", e)))?,
             retry_count: 0,
+            dry_run: req.dry_run,
         })
         .map_err(|e| Error::Internal(format!("Failed to send scan job: {}", e)))?;
     Ok(())
@@ -1,10 +1,19 @@
+use specta::Type;
+
 use crate::router::Error;
 
 use super::AppState;
 
-pub async fn scan_all(ctx: AppState, _: ()) -> Result<(), Error> {
+#[derive(serde::Deserialize, Type)]
+pub struct ScanAllRequest {
+    /// Overrides `dry_run` in config if specified.
+    dry_run: Option<bool>,
+}
+pub async fn scan_all(ctx: AppState, req: ScanAllRequest) -> Result<(), Error> {
     ctx.job_sender
-        .send(crate::JobCommand::ScanAll)
+        .send(crate::JobCommand::ScanAll {
+            dry_run: req.dry_run,
+        })
         .map_err(|e| {
             Error::Internal(format!(
                 "Internal server error: failed to send job command: {}",
@@ -0,0 +1,42 @@
+use specta::Type;
+
+use crate::interface::candidate::{ScanCandidate, ScanCandidateRaw, ScoreBreakdown};
+use crate::router::Error;
+use crate::POOL;
+
+use super::AppState;
+
+#[derive(serde::Deserialize, Type, Debug)]
+pub struct ScanCandidatesRequest {
+    log_id: i32,
+}
+
+#[tracing::instrument(err, skip(_ctx))]
+pub async fn scan_candidates(
+    _ctx: AppState,
+    req: ScanCandidatesRequest,
+) -> Result<Vec<ScanCandidate>, Error> {
+    let res = sqlx::query_as!(
+        ScanCandidateRaw,
+        r#"
+            SELECT
+                id as "id!: i64",
+                log_id,
+                rank,
+                recording_id,
+                recording_title,
+                release_id,
+                release_title,
+                artist,
+                score_breakdown as "score_breakdown: sqlx::types::Json<ScoreBreakdown>"
+            FROM scan_candidate
+            WHERE log_id = ?
+            ORDER BY rank"#,
+        req.log_id
+    )
+    .fetch_all(&*POOL)
+    .await
+    .map_err(|e| Error::Internal(format!("Failed to query db: {:?}", e)))?;
+
+    Ok(res.into_iter().map(|x| x.into()).collect())
+}
@@ -1,5 +1,6 @@
 use specta::Type;
 
+use crate::interface::candidate::ScoreBreakdown;
 use crate::interface::log::{LogType, ScanLog, ScanLogRaw};
 use crate::interface::metadata::Metadata;
 use crate::router::Error;
@@ -30,7 +31,8 @@ pub async fn scan_log(_ctx: AppState, req: ScanLogRequest) -> Result<(Vec<ScanLo
                 source_path,
                 target_path,
                 acoustid_score,
-                retry_count
+                retry_count,
+                score_breakdown as "score_breakdown?: sqlx::types::Json<ScoreBreakdown>"
             FROM log
             WHERE success = COALESCE(?, success)
             ORDER BY id DESC 
@@ -20,5 +20,10 @@ pub async fn scan_log_clear(_ctx: AppState, req: ScanLogClearRequest) -> Result<
     .await
     .map_err(|e| Error::Internal(format!("Failed to delete logs: {:?}", e)))?;
 
+    sqlx::query!("DELETE FROM scan_candidate WHERE log_id NOT IN (SELECT id FROM log)")
+        .execute(&*POOL)
+        .await
+        .map_err(|e| Error::Internal(format!("Failed to delete candidates: {:?}", e)))?;
+
     Ok(())
 }
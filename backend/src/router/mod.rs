@@ -54,6 +54,10 @@ pub async fn start_server(job_sender: JobSender) -> eyre::Result<()> {
             "scan_log_clear",
             Procedure::builder().mutation(handlers::scan_log_clear::scan_log_clear),
         )
+        .procedure(
+            "scan_candidates",
+            Procedure::builder().query(handlers::scan_candidates::scan_candidates),
+        )
         .procedure(
             "queue_info",
             Procedure::builder().query(handlers::queue_info::queue_info),
@@ -74,6 +78,26 @@ pub async fn start_server(job_sender: JobSender) -> eyre::Result<()> {
         .procedure(
             "fix_failed",
             Procedure::builder().mutation(handlers::fix::fix_failed),
+        )
+        .procedure(
+            "proposal_apply",
+            Procedure::builder().mutation(handlers::proposal_apply::proposal_apply),
+        )
+        .procedure(
+            "refresh_library",
+            Procedure::builder().mutation(handlers::refresh_library::refresh_library),
+        )
+        .procedure(
+            "revert",
+            Procedure::builder().mutation(handlers::revert::revert),
+        )
+        .procedure(
+            "cache_info",
+            Procedure::builder().query(handlers::cache::cache_info),
+        )
+        .procedure(
+            "cache_purge",
+            Procedure::builder().mutation(handlers::cache::cache_purge),
         );
     let (procedures, types) = router.build().unwrap();
 
@@ -8,6 +8,7 @@ use notify::event::ModifyKind;
 use notify::{
     event::AccessKind, Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher,
 };
+use once_cell::sync::Lazy;
 use tokio::sync::mpsc::{channel, Receiver};
 use tokio::time::{sleep, Duration};
 use tracing::{info, warn};
@@ -15,6 +16,24 @@ use tracing::{info, warn};
 use crate::config::CONFIG;
 use crate::{job::JobCommand, JobSender};
 
+/// Files in source dir written by jobs (e.g. tagged in place).
+/// Events of these files are ignored for a while so that they are not scanned again.
+static WRITTEN_FILES: Lazy<Mutex<HashMap<PathBuf, SystemTime>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+pub fn mark_written(path: &Path) {
+    WRITTEN_FILES
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), SystemTime::now());
+}
+
+fn is_recently_written(path: &Path) -> bool {
+    let mut written = WRITTEN_FILES.lock().unwrap();
+    written.retain(|_, time| time.elapsed().is_ok_and(|e| e.as_secs() < 10));
+    written.contains_key(path)
+}
+
 pub fn async_watcher() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)> {
     let (tx, rx) = channel(1);
 
@@ -44,6 +63,9 @@ pub async fn start_watcher(job_sender: JobSender) -> notify::Result<()> {
                         let job_sender = job_sender.clone();
                         tokio::spawn(async move {
                             sleep(Duration::from_secs(1)).await;
+                            if is_recently_written(&path) {
+                                return;
+                            }
                             if let Some(last_modified) =
                                 file_last_modified.lock().unwrap().get(&path)
                             {
@@ -57,6 +79,7 @@ pub async fn start_watcher(job_sender: JobSender) -> notify::Result<()> {
                                 .send(JobCommand::Scan {
                                     path,
                                     retry_count: 0,
+                                    dry_run: None,
                                 })
                                 .unwrap();
                         });
@@ -82,6 +105,9 @@ pub async fn start_watcher(job_sender: JobSender) -> notify::Result<()> {
                             let file_last_modified = file_last_modified.clone();
                             tokio::spawn(async move {
                                 sleep(Duration::from_secs(1)).await;
+                                if is_recently_written(&path) {
+                                    return;
+                                }
                                 if let Some(last_modified) =
                                     file_last_modified.lock().unwrap().get(&path)
                                 {
@@ -95,6 +121,7 @@ pub async fn start_watcher(job_sender: JobSender) -> notify::Result<()> {
                                     .send(JobCommand::Scan {
                                         path,
                                         retry_count: 0,
+                                        dry_run: None,
                                     })
                                     .unwrap();
                             });
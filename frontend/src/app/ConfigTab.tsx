@@ -1,4 +1,4 @@
-import { Button, Textarea } from "@mantine/core";
+import { Button, Group, Text, Textarea } from "@mantine/core";
 import { notifications } from "@mantine/notifications";
 import { rspc } from "../lib/client";
 import { useRef } from "react";
@@ -7,6 +7,13 @@ export function ConfigTab() {
   const { data: config } = rspc.useQuery(["config_read", null]);
   const { mutateAsync: writeConfig } = rspc.useMutation("config_write");
   const ref = useRef<HTMLTextAreaElement>(null);
+  const { data: cacheInfo, refetch: refetchCacheInfo } = rspc.useQuery([
+    "cache_info",
+    null,
+  ]);
+  const { mutateAsync: purgeCache } = rspc.useMutation("cache_purge", {
+    onSuccess: () => refetchCacheInfo(),
+  });
 
   return (
     <div className="flex flex-col gap-2 p-2">
@@ -22,6 +29,9 @@ export function ConfigTab() {
             variant="filled"
             spellCheck={false}
           />
+          <Text size="xs" c="dimmed">
+            Changes to concurrency take effect after restart.
+          </Text>
           <Button
             onClick={async () => {
               if (ref.current?.value) {
@@ -37,6 +47,30 @@ export function ConfigTab() {
           </Button>
         </>
       )}
+      {cacheInfo && (
+        <Group>
+          <Text size="sm">
+            Cache: {cacheInfo.count} responses (
+            {(cacheInfo.size / 1024 / 1024).toFixed(1)} MB,{" "}
+            {cacheInfo.expired_count} expired)
+          </Text>
+          <Button
+            size="xs"
+            variant="light"
+            onClick={() => purgeCache({ expired_only: true })}
+          >
+            Purge expired
+          </Button>
+          <Button
+            size="xs"
+            variant="light"
+            color="red"
+            onClick={() => purgeCache({ expired_only: false })}
+          >
+            Purge all
+          </Button>
+        </Group>
+      )}
     </div>
   );
 }
@@ -1,5 +1,5 @@
 import { ScanLog } from "../../lib/bindings";
-import { Button, TextInput } from "@mantine/core";
+import { Button, Table, TextInput } from "@mantine/core";
 import { useForm } from "@mantine/form";
 import { rspc } from "../../lib/client";
 import { notifications } from "@mantine/notifications";
@@ -13,11 +13,74 @@ export function FixModal(props: { scanLog: ScanLog }) {
     },
   });
 
+  const { data: candidates } = rspc.useQuery([
+    "scan_candidates",
+    { log_id: props.scanLog.id },
+  ]);
   const { mutateAsync: fix } = rspc.useMutation("fix");
   const { mutateAsync: fixFailed } = rspc.useMutation("fix_failed");
 
+  const submit = async (ids: {
+    release_id: string | null;
+    recording_id: string | null;
+    candidate_id: number | null;
+  }) => {
+    if (props.scanLog.success) {
+      if (props.scanLog.target_path) {
+        await fix({ target_path: props.scanLog.target_path, ...ids });
+      } else {
+        throw new Error("target_path is null");
+      }
+    } else {
+      await fixFailed({ source_path: props.scanLog.source_path, ...ids });
+    }
+    notifications.show({
+      title: "Success",
+      message: "Send request",
+    });
+    modals.closeAll();
+  };
+
   return (
     <>
+      {candidates && candidates.length > 0 && (
+        <Table>
+          <Table.Thead>
+            <Table.Tr>
+              <Table.Th>recording</Table.Th>
+              <Table.Th>release</Table.Th>
+              <Table.Th>score</Table.Th>
+              <Table.Th />
+            </Table.Tr>
+          </Table.Thead>
+          <Table.Tbody>
+            {candidates.map((c) => (
+              <Table.Tr key={c.id}>
+                <Table.Td>
+                  {c.recording_title}
+                  {c.artist && ` / ${c.artist}`}
+                </Table.Td>
+                <Table.Td>{c.release_title}</Table.Td>
+                <Table.Td>{c.score.total.toFixed(2)}</Table.Td>
+                <Table.Td>
+                  <Button
+                    size="xs"
+                    variant="subtle"
+                    onClick={() =>
+                      submit({
+                        release_id: null,
+                        recording_id: null,
+                        candidate_id: c.id,
+                      })}
+                  >
+                    Use
+                  </Button>
+                </Table.Td>
+              </Table.Tr>
+            ))}
+          </Table.Tbody>
+        </Table>
+      )}
       <TextInput
         label="Musicbrainz release id"
         {...form.getInputProps("releaseId")}
@@ -28,30 +91,12 @@ export function FixModal(props: { scanLog: ScanLog }) {
       />
       <Button
         fullWidth
-        onClick={async () => {
-          if (props.scanLog.success) {
-            if (props.scanLog.target_path) {
-              await fix({
-                target_path: props.scanLog.target_path,
-                recording_id: form.values.recordingId,
-                release_id: form.values.releaseId,
-              });
-            } else {
-              throw new Error("target_path is null");
-            }
-          } else {
-            await fixFailed({
-              source_path: props.scanLog.source_path,
-              recording_id: form.values.recordingId,
-              release_id: form.values.releaseId,
-            });
-          }
-          notifications.show({
-            title: "Success",
-            message: "Send request",
-          });
-          modals.closeAll();
-        }}
+        onClick={() =>
+          submit({
+            recording_id: form.values.recordingId,
+            release_id: form.values.releaseId,
+            candidate_id: null,
+          })}
         mt="md"
       >
         Submit
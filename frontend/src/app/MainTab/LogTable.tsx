@@ -3,6 +3,8 @@ import { ScanLog } from "../../lib/bindings";
 import { useState } from "react";
 import { Button } from "@mantine/core";
 import { openFixModal } from "./openFixModal";
+import { rspc } from "../../lib/client";
+import { notifications } from "@mantine/notifications";
 
 export function LogTable(
   props: {
@@ -17,6 +19,8 @@ export function LogTable(
   },
 ) {
   const [selectedId, setSelectedId] = useState<number | null>(null);
+  const { mutateAsync: applyProposal } = rspc.useMutation("proposal_apply");
+  const { mutateAsync: revert } = rspc.useMutation("revert");
   return (
     <div
       className={props.className}
@@ -36,6 +40,7 @@ export function LogTable(
             render: ({ created_at }) =>
               new Date(created_at * 1000).toLocaleString(),
           },
+          { accessor: "type" },
           {
             accessor: "success",
             render: ({ success }) =>
@@ -53,14 +58,54 @@ export function LogTable(
           {
             accessor: "fix",
             render: (log) => {
+              if (log.type == "Proposal" && log.success) {
+                return (
+                  <Button
+                    size="xs"
+                    variant="subtle"
+                    onClick={async (e) => {
+                      e.stopPropagation();
+                      await applyProposal({ ids: [log.id] });
+                      notifications.show({
+                        title: "Success",
+                        message: "Send request",
+                      });
+                    }}
+                  >
+                    Apply
+                  </Button>
+                );
+              }
+              if (log.type != "Scan" && log.type != "Fix") {
+                return null;
+              }
               return (
-                <Button
-                  size="xs"
-                  variant="subtle"
-                  onClick={() => openFixModal(log)}
-                >
-                  Fix
-                </Button>
+                <>
+                  <Button
+                    size="xs"
+                    variant="subtle"
+                    onClick={() => openFixModal(log)}
+                  >
+                    Fix
+                  </Button>
+                  {log.success && log.target_path && (
+                    <Button
+                      size="xs"
+                      variant="subtle"
+                      color="red"
+                      onClick={async (e) => {
+                        e.stopPropagation();
+                        await revert({ id: log.id });
+                        notifications.show({
+                          title: "Success",
+                          message: "Send request",
+                        });
+                      }}
+                    >
+                      Revert
+                    </Button>
+                  )}
+                </>
               );
             },
           },
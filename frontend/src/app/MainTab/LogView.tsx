@@ -31,6 +31,36 @@ export function LogView({ log }: { log: ScanLog }) {
                 ],
               }}
             />
+            {log.score_breakdown && (
+              <>
+                <h3 className="text-lg">Score</h3>
+                <Table
+                  data={{
+                    head: [
+                      "component",
+                      "value",
+                      "weight",
+                      "contribution",
+                    ],
+                    body: [
+                      ...log.score_breakdown.components.map((c) => [
+                        c.name,
+                        c.value.toFixed(3),
+                        c.weight,
+                        c.contribution.toFixed(3),
+                      ]),
+                      ["total", "", "", log.score_breakdown.total.toFixed(3)],
+                      [
+                        "acoustid",
+                        log.score_breakdown.acoustid_score?.toFixed(3) ?? "-",
+                        "",
+                        "",
+                      ],
+                    ],
+                  }}
+                />
+              </>
+            )}
             <h3 className="text-lg">Metadata</h3>
             <Table
               data={{
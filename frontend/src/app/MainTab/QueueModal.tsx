@@ -11,7 +11,32 @@ export function QueueModal(
       return {
         id: i + 1,
         type: "Scan",
-        message: `path:${t.Scan.path}, retry_count:${t.Scan.retry_count}`,
+        message:
+          `path:${t.Scan.path}, retry_count:${t.Scan.retry_count}, dry_run:${t.Scan.dry_run}`,
+      };
+    } else if ("ScanAlbum" in t) {
+      return {
+        id: i + 1,
+        type: "ScanAlbum",
+        message: `dir:${t.ScanAlbum.dir}, dry_run:${t.ScanAlbum.dry_run}`,
+      };
+    } else if ("ApplyProposal" in t) {
+      return {
+        id: i + 1,
+        type: "Apply",
+        message: `log_id:${t.ApplyProposal.log_id}`,
+      };
+    } else if ("Revert" in t) {
+      return {
+        id: i + 1,
+        type: "Revert",
+        message: `log_id:${t.Revert.log_id}`,
+      };
+    } else if ("Refresh" in t) {
+      return {
+        id: i + 1,
+        type: "Refresh",
+        message: `path:${t.Refresh.path}`,
       };
     } else {
       return {
@@ -1,4 +1,4 @@
-import { Button, TextInput } from "@mantine/core";
+import { Button, Checkbox, TextInput } from "@mantine/core";
 import { useForm } from "@mantine/form";
 import { rspc } from "../../lib/client";
 import { notifications } from "@mantine/notifications";
@@ -7,12 +7,14 @@ export function ScanForm() {
   const form = useForm({
     initialValues: {
       scanPath: "",
+      dryRun: false,
     },
   });
 
   const { mutateAsync: scan } = rspc.useMutation("scan");
   const { mutateAsync: clearQueue } = rspc.useMutation("queue_clear");
   const { mutateAsync: scanAll } = rspc.useMutation("scan_all");
+  const { mutateAsync: refreshLibrary } = rspc.useMutation("refresh_library");
 
   return (
     <div className="flex flex-col gap-2">
@@ -25,7 +27,10 @@ export function ScanForm() {
         <Button
           onClick={async () => {
             try {
-              await scan({ path: form.values.scanPath });
+              await scan({
+                path: form.values.scanPath,
+                dry_run: form.values.dryRun || null,
+              });
               notifications.show({
                 title: "Success",
                 message: "Send request",
@@ -41,11 +46,15 @@ export function ScanForm() {
           Scan
         </Button>
       </div>
-      <div className="flex gap-2">
+      <div className="flex gap-2 items-center">
+        <Checkbox
+          label="Dry run"
+          {...form.getInputProps("dryRun", { type: "checkbox" })}
+        />
         <Button
           onClick={async () => {
             try {
-              await scanAll(null);
+              await scanAll({ dry_run: form.values.dryRun || null });
               notifications.show({
                 title: "Success",
                 message: "Send request",
@@ -60,6 +69,24 @@ export function ScanForm() {
         >
           Scan all files
         </Button>
+        <Button
+          onClick={async () => {
+            try {
+              await refreshLibrary(null);
+              notifications.show({
+                title: "Success",
+                message: "Send request",
+              });
+            } catch (e: any) {
+              notifications.show({
+                title: "Error",
+                message: e.message ?? "Unknown error",
+              });
+            }
+          }}
+        >
+          Refresh library
+        </Button>
         <Button
           onClick={async () => {
             try {
@@ -120,7 +120,15 @@ function QueueInfo(props: { queueInfo: QueueInfoType }) {
               tasks[0]
                 ? "Scan" in tasks[0]
                   ? tasks[0].Scan.path
-                  : tasks[0].Fix.path
+                  : "ScanAlbum" in tasks[0]
+                  ? tasks[0].ScanAlbum.dir
+                  : "Fix" in tasks[0]
+                  ? tasks[0].Fix.path
+                  : "Revert" in tasks[0]
+                  ? `revert #${tasks[0].Revert.log_id}`
+                  : "Refresh" in tasks[0]
+                  ? tasks[0].Refresh.path
+                  : `proposal #${tasks[0].ApplyProposal.log_id}`
                 : null,
             ],
           ],